@@ -0,0 +1,219 @@
+//! Async versions of the split and batch tools
+//!
+//! These only depend on the futures crate, so they run on whatever executor the caller is using.
+
+use crate::local::*;
+use crate::rule::Waves;
+
+use futures::{
+  future::{self, BoxFuture, Either, Future, FutureExt},
+  stream::{self, Stream, StreamExt},
+};
+use std::time::Duration;
+
+/// The check run by an async rule, with the error already turned into a message
+type AsyncCheck<T> = Box<dyn for<'a> Fn(&'a T) -> BoxFuture<'a, Result<(), String>> + Send + Sync>;
+
+/// A named check that needs to wait on something, such as a uniqueness lookup in a database
+///
+/// Example:
+/// ```rust
+/// use allwhat::{prelude::*, AsyncRule};
+/// use futures::FutureExt;
+///
+/// async fn is_taken(name: &str) -> bool {
+///   name == "admin"
+/// }
+///
+/// let rules = vec![AsyncRule::new("unique", |name: &String| {
+///   async move {
+///     match is_taken(name).await {
+///       true => Err(format!("'{}' is already taken", name)),
+///       false => Ok(()),
+///     }
+///   }
+///   .boxed()
+/// })];
+///
+/// let batch = futures::executor::block_on(BatchResult::validate_async("admin".to_string(), &rules, 4));
+/// assert_eq!(batch.failed_rules().collect::<Vec<_>>(), vec!["unique"]);
+/// ```
+pub struct AsyncRule<T> {
+  name: String,
+  description: Option<String>,
+  timeout: Option<Duration>,
+  requires: Vec<String>,
+  check: AsyncCheck<T>,
+}
+
+impl<T> Debug for AsyncRule<T> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.debug_struct("AsyncRule")
+      .field("name", &self.name)
+      .field("description", &self.description)
+      .field("timeout", &self.timeout)
+      .field("requires", &self.requires)
+      .finish()
+  }
+}
+
+impl<T> AsyncRule<T> {
+  /// Create a rule from a name and a check returning a boxed future
+  ///
+  /// The future may borrow the value, so `async move { .. }.boxed()` works as the check's body.
+  pub fn new<E, F>(name: &str, check: F) -> AsyncRule<T>
+  where
+    F: for<'a> Fn(&'a T) -> BoxFuture<'a, Result<(), E>> + Send + Sync + 'static,
+    E: Display + 'static,
+  {
+    AsyncRule {
+      name: name.to_string(),
+      description: None,
+      timeout: None,
+      requires: vec![],
+      check: Box::new(move |value| {
+        check(value)
+          .map(|res| res.map_err(|err| err.to_string()))
+          .boxed()
+      }),
+    }
+  }
+
+  /// Explain what the rule is for, which is kept alongside its outcome
+  pub fn describe(self, description: &str) -> AsyncRule<T> {
+    AsyncRule {
+      description: Some(description.to_string()),
+      ..self
+    }
+  }
+
+  /// Fail the rule if its check takes longer than this
+  pub fn timeout(self, timeout: Duration) -> AsyncRule<T> {
+    AsyncRule {
+      timeout: Some(timeout),
+      ..self
+    }
+  }
+
+  /// Only run this rule if the named rule passes, as with Rule::requires
+  pub fn requires(mut self, name: &str) -> AsyncRule<T> {
+    self.requires.push(name.to_string());
+    self
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn description(&self) -> Option<&str> {
+    self.description.as_deref()
+  }
+
+  /// The names of the rules this one requires
+  pub fn prerequisites(&self) -> &[String] {
+    &self.requires
+  }
+
+  /// Run the check against a value, ignoring any prerequisites, giving up once the timeout has passed
+  pub async fn check(&self, value: &T) -> RuleOutcome {
+    let check = (self.check)(value);
+    let result = match self.timeout {
+      None => check.await,
+      Some(timeout) => match future::select(check, futures_timer::Delay::new(timeout)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(format!("timed out after {:?}", timeout)),
+      },
+    };
+    match result {
+      Ok(()) => RuleOutcome::Passed,
+      Err(msg) => RuleOutcome::Failed(msg),
+    }
+  }
+
+  fn report(&self, outcome: RuleOutcome) -> RuleReport {
+    RuleReport {
+      name: self.name.clone(),
+      description: self.description.clone(),
+      outcome,
+    }
+  }
+}
+
+impl<T: Sync> BatchResult<T> {
+  /// An async BatchResult::validate_rules, with at most limit of the rules running at once
+  ///
+  /// The rules may finish in any order, but their outcomes and errors are kept in the order they
+  /// were given. A rule with prerequisites waits until they have all finished, and is skipped if
+  /// any of them didn't pass. A limit of zero is treated as one.
+  pub async fn validate_async(value: T, rules: &[AsyncRule<T>], limit: usize) -> BatchResult<T> {
+    let heads: Vec<_> = rules
+      .iter()
+      .map(|rule| (rule.name(), rule.prerequisites()))
+      .collect();
+    let mut waves = Waves::new(&heads);
+    while let Some(wave) = waves.next_ready() {
+      let results = stream::iter(&wave)
+        .map(|&index| rules[index].check(&value))
+        .buffered(limit.max(1))
+        .collect()
+        .await;
+      waves.record(&wave, results);
+    }
+
+    let mut result = BatchResult::new(value);
+    for (rule, outcome) in rules.iter().zip(waves.into_outcomes()) {
+      result.record_rule(rule.report(outcome));
+    }
+    result
+  }
+}
+
+impl<T> SplitResult<T> {
+  /// Run a stream of futures, with at most limit of them in flight at once
+  ///
+  /// The futures may finish in any order, but the values and errors are kept in the order of the
+  /// stream. A limit of zero is treated as one.
+  pub async fn from_stream<S, Fut, E>(stream: S, limit: usize) -> SplitResult<T>
+  where
+    S: Stream<Item = Fut>,
+    Fut: Future<Output = Result<T, E>>,
+    E: Display + Debug + Sync + Send + 'static,
+  {
+    let results = stream.buffered(limit.max(1)).enumerate();
+    futures::pin_mut!(results);
+
+    let mut split = SplitResult::empty();
+    while let Some((index, result)) = results.next().await {
+      split.add(index, result);
+    }
+    split
+  }
+
+  /// Run a list of futures, with at most limit of them in flight at once
+  ///
+  /// Example:
+  /// ```rust
+  /// use allwhat::SplitResult;
+  ///
+  /// async fn lookup(id: u32) -> Result<String, String> {
+  ///   match id % 3 {
+  ///     0 => Err(format!("No user {}", id)),
+  ///     _ => Ok(format!("User {}", id)),
+  ///   }
+  /// }
+  ///
+  /// let split = futures::executor::block_on(SplitResult::from_futures((1..=6).map(lookup), 2));
+  /// assert_eq!(split.values(), &["User 1", "User 2", "User 4", "User 5"]);
+  /// assert_eq!(split.failed_indices(), &[2, 5]);
+  /// ```
+  pub async fn from_futures<Fut, E>(
+    futures: impl IntoIterator<Item = Fut>,
+    limit: usize,
+  ) -> SplitResult<T>
+  where
+    Fut: Future<Output = Result<T, E>>,
+    E: Display + Debug + Sync + Send + 'static,
+  {
+    SplitResult::from_stream(stream::iter(futures), limit).await
+  }
+}
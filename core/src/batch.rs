@@ -11,61 +11,41 @@
 
 use crate::local::*;
 
+use crate::{budget::remaining, rule::run_in_waves};
+
+#[cfg(feature = "try_mut")]
+use crate::try_mut::{PoisonedErr, TryMut};
+
 /// Tells the caller whether the restore after an error
 /// Gather the results from applying multiple
 #[derive(Debug)]
+#[cfg_attr(feature = "serializable", derive(serde::Serialize))]
 pub struct BatchResult<T> {
-  count: u32,
+  count: u64,
   value: T,
   errors: ErrorGroup,
+  aborted: Option<Aborted>,
+  /// The outcome of each named rule, in the order they were run
+  rules: Vec<RuleReport>,
+  /// Step timings, only kept by the timed constructors
+  metrics: Option<Metrics>,
+  /// Failures that couldn't be rolled back, leaving the value in an unknown state
+  fatal: Option<ErrorGroup>,
+  /// Nested batches, such as one for each line of an order, with their values dropped
+  children: Vec<(String, BatchResult<()>)>,
 }
 
-// impl<T> TryMut for BatchResult<T>
-// where
-//   T: TryMut,
-// {
-//   type Error = AnyhowError;
-//   type Backup = ();
-
-//   // fn backup<B>(&self) -> B {
-//   //   self.value.backup()
-//   // }
-
-//   // /// Return the object to its previous state because of an error
-//   // fn restore<B>(&mut self, backup: B) -> () {
-//   //   self.value.restore(backup)
-//   // }
-
-//   ///
-//   fn try_mut<E, F>(&mut self, _func: F) -> Result<(), Self::Error>
-//   where
-//     E: Into<AnyhowError>,
-//     F: FnMut(&mut Self) -> Result<(), E>,
-//   {
-//     unimplemented!("BatchResult should only use try_mut_quiet")
-//   }
-
-//   fn try_mut_quiet<E, F>(&mut self, mut func: F)
-//   where
-//     E: Into<Self::Error>,
-//     F: FnMut(Self) -> Result<(), E>,
-//   {
-//     // This increments regardless and doesn't get rolled back
-//     self.count += 1;
-//     // let backup: B = self.backup();
-//     if let Err(err) = func(&mut self) {
-//       self.append(err);
-//       // self.restore(backup);
-//     }
-//   }
-// }
-
 impl<T> BatchResult<T> {
   pub fn new(init: T) -> Self {
     BatchResult {
       count: 0,
       value: init,
       errors: ErrorGroup::new(Some("Batch Errors".to_string())),
+      aborted: None,
+      rules: vec![],
+      metrics: None,
+      fatal: None,
+      children: vec![],
     }
   }
 
@@ -77,19 +57,28 @@ impl<T> BatchResult<T> {
     }
   }
 
-  /// The number of errors accumulated
-  pub fn count_error(&self) -> u32 {
-    self.errors.len() as u32
+  /// The number of errors accumulated, including those of any children
+  pub fn count_error(&self) -> u64 {
+    let children: u64 = self
+      .children
+      .iter()
+      .map(|(_, child)| child.count_error())
+      .sum();
+    self.errors.len() as u64 + children
   }
 
   /// The number of successful functions run against this result
-  pub fn count_valid(&self) -> u32 {
-    self.count - self.count_error()
+  ///
+  /// Errors added with append aren't counted as functions run, so this stops at zero rather than
+  /// going below it.
+  pub fn count_valid(&self) -> u64 {
+    self.count().saturating_sub(self.count_error())
   }
 
-  /// The total number of functions run against this result
-  pub fn count(&self) -> u32 {
-    self.count
+  /// The total number of functions run against this result, including those of any children
+  pub fn count(&self) -> u64 {
+    let children: u64 = self.children.iter().map(|(_, child)| child.count()).sum();
+    self.count + children
   }
 
   /// Whether any errors were found in the batch
@@ -97,6 +86,190 @@ impl<T> BatchResult<T> {
     self.count_error() == 0
   }
 
+  /// The accumulated value, which is kept even when there are errors
+  pub fn value(&self) -> &T {
+    &self.value
+  }
+
+  /// The steps whose rollback also failed, if there were any
+  ///
+  /// These are counted among the regular errors too, but they mean the value may have been left
+  /// half changed and shouldn't be trusted.
+  pub fn fatal_errors(&self) -> Option<&ErrorGroup> {
+    self.fatal.as_ref()
+  }
+
+  /// Whether a failed rollback has left the value in an unknown state, here or in a child
+  pub fn is_poisoned(&self) -> bool {
+    self.fatal.is_some() || self.children.iter().any(|(_, child)| child.is_poisoned())
+  }
+
+  /// The nested batches and their labels, in the order they were added
+  pub fn children(&self) -> impl Iterator<Item = (&str, &BatchResult<()>)> {
+    self
+      .children
+      .iter()
+      .map(|(label, child)| (label.as_str(), child))
+  }
+
+  /// Nest another batch under this one, keeping its counts and errors under the label
+  ///
+  /// The child's value is dropped, so batches over different types can be gathered into one
+  /// report, such as a batch for an order with one child for each of its lines.
+  pub fn push_child<U>(&mut self, label: &str, child: BatchResult<U>) {
+    self
+      .children
+      .push((label.to_string(), child.map_value(|_| ())));
+  }
+
+  /// Add the steps, errors and children of another batch into this one
+  ///
+  /// Unlike push_child, the other batch's errors join this one's directly rather than being
+  /// nested. Its value is dropped.
+  pub fn merge<U>(mut self, other: BatchResult<U>) -> BatchResult<T> {
+    self.count += other.count;
+    self.errors.merge(other.errors);
+    self.rules.extend(other.rules);
+    self.children.extend(other.children);
+    self.aborted = self.aborted.or(other.aborted);
+    if let Some(fatal) = other.fatal {
+      self
+        .fatal
+        .get_or_insert_with(|| ErrorGroup::new(fatal.label().map(str::to_string)))
+        .merge(fatal);
+    }
+    self.metrics = match (self.metrics, other.metrics) {
+      (Some(mut metrics), Some(other)) => {
+        metrics.merge(other);
+        Some(metrics)
+      }
+      (metrics, other) => metrics.or(other),
+    };
+    self
+  }
+
+  /// Swap the value for another, keeping everything else
+  fn map_value<U, F: FnOnce(T) -> U>(self, func: F) -> BatchResult<U> {
+    BatchResult {
+      count: self.count,
+      value: func(self.value),
+      errors: self.errors,
+      aborted: self.aborted,
+      rules: self.rules,
+      metrics: self.metrics,
+      fatal: self.fatal,
+      children: self.children,
+    }
+  }
+
+  /// All the errors, with those of each child nested in a group under its label
+  fn all_errors(self) -> ErrorGroup {
+    let mut errors = self.errors;
+    for (label, child) in self.children {
+      if !child.is_ok() {
+        errors.push_group(child.all_errors().set_label(label));
+      }
+    }
+    errors
+  }
+
+  /// Write a line for this batch and its own errors, then each child one level deeper
+  fn write_report(&self, f: &mut Formatter, label: &str, depth: usize) -> std::fmt::Result {
+    let indent = "\t".repeat(depth);
+    let status = match self.count_error() {
+      0 => "ok".to_string(),
+      1 => "1 error".to_string(),
+      errors => format!("{} errors", errors),
+    };
+    let skipped = match self.count_skipped() {
+      0 => String::new(),
+      skipped => format!(", {} skipped", skipped),
+    };
+    writeln!(
+      f,
+      "{}{}: {} of {} passed{}, {}",
+      indent,
+      label,
+      self.count_valid(),
+      self.count(),
+      skipped,
+      status
+    )?;
+    for (i, err) in self.errors.iter().enumerate() {
+      writeln!(f, "{}\t{}) {}", indent, i + 1, err)?;
+    }
+    for (label, child) in &self.children {
+      child.write_report(f, label, depth + 1)?;
+    }
+    Ok(())
+  }
+
+  /// Details of why the batch stopped early, if an error budget was broken
+  pub fn aborted(&self) -> Option<&Aborted> {
+    self.aborted.as_ref()
+  }
+
+  /// How long each step took, if the batch was built by one of the timed constructors
+  pub fn metrics(&self) -> Option<&Metrics> {
+    self.metrics.as_ref()
+  }
+
+  /// Attach the timings of the steps that built this result
+  pub(crate) fn with_metrics(self, metrics: Metrics) -> BatchResult<T> {
+    BatchResult {
+      metrics: Some(metrics),
+      ..self
+    }
+  }
+
+  /// The outcome of every named rule run by validate_rules, in the order they were run
+  pub fn rule_reports(&self) -> &[RuleReport] {
+    &self.rules
+  }
+
+  /// The outcome of a named rule, or None if no rule by that name was run
+  pub fn rule_outcome(&self, name: &str) -> Option<&RuleOutcome> {
+    self
+      .rules
+      .iter()
+      .find(|report| report.name == name)
+      .map(|report| &report.outcome)
+  }
+
+  /// The names of the rules that failed
+  pub fn failed_rules(&self) -> impl Iterator<Item = &str> {
+    self
+      .rules
+      .iter()
+      .filter(|report| report.outcome.is_failed())
+      .map(|report| report.name.as_str())
+  }
+
+  /// The names of the rules that were skipped because a prerequisite didn't pass
+  pub fn skipped_rules(&self) -> impl Iterator<Item = &str> {
+    self
+      .rules
+      .iter()
+      .filter(|report| report.outcome.is_skipped())
+      .map(|report| report.name.as_str())
+  }
+
+  /// The number of rules skipped, including those of any children
+  ///
+  /// Skipped rules were never run, so they aren't part of count, count_valid or count_error.
+  pub fn count_skipped(&self) -> u64 {
+    let children: u64 = self
+      .children
+      .iter()
+      .map(|(_, child)| child.count_skipped())
+      .sum();
+    let own = self
+      .rules
+      .iter()
+      .filter(|report| report.outcome.is_skipped());
+    own.count() as u64 + children
+  }
+
   /// Add an additional error to the batch result
   pub fn append<E>(&mut self, err: E)
   where
@@ -122,9 +295,72 @@ impl<T> BatchResult<T> {
 
     BatchResult {
       count,
-      value,
       errors,
+      ..BatchResult::new(value)
+    }
+  }
+
+  /// A result for a value that has already been put through count checks
+  pub(crate) fn from_checks(value: T, count: u64, errors: ErrorGroup) -> BatchResult<T> {
+    BatchResult {
+      count,
+      errors,
+      ..BatchResult::new(value)
+    }
+  }
+
+  /// Run the value through a list of named rules, keeping the outcome of each
+  ///
+  /// Failures are added to the error group prefixed with the rule's name, and every outcome can
+  /// be looked up afterwards with rule_outcome.
+  pub fn validate_rules<'a>(
+    value: T,
+    rules: impl IntoIterator<Item = &'a Rule<T>>,
+  ) -> BatchResult<T>
+  where
+    T: 'a,
+  {
+    let rules: Vec<&Rule<T>> = rules.into_iter().collect();
+    let heads: Vec<_> = rules
+      .iter()
+      .map(|rule| (rule.name(), rule.prerequisites()))
+      .collect();
+    let outcomes = run_in_waves(&heads, |wave| {
+      wave
+        .iter()
+        .map(|&index| rules[index].check(&value))
+        .collect()
+    });
+    BatchResult::new(value).with_rules(rules.into_iter().zip(outcomes))
+  }
+
+  /// Record the outcome of each rule in order
+  pub(crate) fn with_rules<'a>(
+    mut self,
+    outcomes: impl IntoIterator<Item = (&'a Rule<T>, RuleOutcome)>,
+  ) -> BatchResult<T>
+  where
+    T: 'a,
+  {
+    for (rule, outcome) in outcomes {
+      self.record_rule(rule.report(outcome));
     }
+    self
+  }
+
+  /// Keep a rule's outcome, counting it and adding an error if it failed
+  ///
+  /// Skipped rules weren't run, so they are only counted by count_skipped.
+  pub(crate) fn record_rule(&mut self, report: RuleReport) {
+    match &report.outcome {
+      RuleOutcome::Passed => self.count += 1,
+      RuleOutcome::Failed(msg) => {
+        self.count += 1;
+        self.errors.push(format!("{}: {}", report.name, msg));
+      }
+      RuleOutcome::Skipped(_) => (),
+    }
+    self.rules.push(report);
   }
 
   /// Uses a function to apply each item to the accumulator, storing errors for future examination
@@ -143,6 +379,33 @@ impl<T> BatchResult<T> {
     self
   }
 
+  /// Like apply, but puts the value back the way it was if the function fails
+  ///
+  /// A snapshot is taken before the function runs, so a failure never leaves a half applied
+  /// change behind.
+  pub fn apply_or_rollback<Err, Func>(mut self, func: Func) -> BatchResult<T>
+  where
+    T: Snapshot,
+    Func: Fn(&mut T) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    let res = self.try_step(T::snapshot, T::restore, |value| func(value));
+    self.record(res);
+    self
+  }
+
+  /// Like apply_or_rollback, for values that are saved by cloning them
+  pub fn apply_or_rollback_cloned<Err, Func>(mut self, func: Func) -> BatchResult<T>
+  where
+    T: Clone,
+    Func: Fn(&mut T) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    let res = self.try_step(T::clone, |value, saved| *value = saved, |value| func(value));
+    self.record(res);
+    self
+  }
+
   /// Uses a function to apply each item to the accumulator, storing errors for future examination
   ///
   /// Please note, errors have the potential to corrupt the accumulator since it mutates
@@ -166,6 +429,109 @@ impl<T> BatchResult<T> {
     })
   }
 
+  /// Like fold, but undoes each item that fails so the accumulator only holds complete changes
+  pub fn fold_or_rollback<Item, Err, Func>(
+    accumulator: T,
+    list: impl Iterator<Item = Item>,
+    func: Func,
+  ) -> BatchResult<T>
+  where
+    T: Snapshot,
+    Func: Fn(&mut T, Item) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    list.fold(BatchResult::new(accumulator), |mut acc, item| {
+      let res = acc.try_step(T::snapshot, T::restore, |value| func(value, item));
+      acc.record(res);
+      acc
+    })
+  }
+
+  /// Like fold_or_rollback, for accumulators that are saved by cloning them
+  ///
+  /// Every item clones the whole accumulator, so large ones are better off with a Snapshot impl.
+  pub fn fold_or_rollback_cloned<Item, Err, Func>(
+    accumulator: T,
+    list: impl Iterator<Item = Item>,
+    func: Func,
+  ) -> BatchResult<T>
+  where
+    T: Clone,
+    Func: Fn(&mut T, Item) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    list.fold(BatchResult::new(accumulator), |mut acc, item| {
+      let res = acc.try_step(
+        T::clone,
+        |value, saved| *value = saved,
+        |value| func(value, item),
+      );
+      acc.record(res);
+      acc
+    })
+  }
+
+  /// Run a step against the value, restoring what was saved beforehand if it fails
+  fn try_step<Saved, Err, Func>(
+    &mut self,
+    save: fn(&T) -> Saved,
+    restore: fn(&mut T, Saved),
+    func: Func,
+  ) -> Result<(), Err>
+  where
+    Func: FnOnce(&mut T) -> Result<(), Err>,
+  {
+    let saved = save(&self.value);
+    let res = func(&mut self.value);
+    if res.is_err() {
+      restore(&mut self.value, saved);
+    }
+    res
+  }
+
+  /// Fold a list of actions into the accumulator, reverting each one that fails through its patch
+  ///
+  /// Unlike fold_or_rollback, each action decides for itself what to save and how to undo it.
+  /// When the undo fails as well, the step is also recorded in fatal_errors.
+  #[cfg(feature = "try_mut")]
+  pub fn try_fold(accumulator: T, list: impl Iterator<Item = T::Action>) -> BatchResult<T>
+  where
+    T: TryMut,
+  {
+    let mut acc = BatchResult::new(accumulator);
+    for mut action in list {
+      // The outcome is already recorded in the batch
+      let _ = acc.try_mut(&mut action);
+    }
+    acc
+  }
+
+  /// Like fold, but stops early once the error budget is spent
+  ///
+  /// The rest of the list is counted without being applied, so the result can report how much
+  /// work was skipped.
+  pub fn fold_with_budget<Item, Err, Func>(
+    accumulator: T,
+    list: impl Iterator<Item = Item>,
+    budget: &ErrorBudget<Err>,
+    func: Func,
+  ) -> BatchResult<T>
+  where
+    Func: Fn(&mut T, Item) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    let mut acc = BatchResult::new(accumulator);
+    let mut list = list;
+    for item in &mut list {
+      let res = func(&mut acc.value, item);
+      if acc.step_budget(res, budget) {
+        acc.abort_remaining(&list);
+        break;
+      }
+    }
+    acc
+  }
+
   /// Equivalent of a for loop, capturing all errors into a single batch
   ///
   /// If a value is expected to be returned, SplitResult should be used
@@ -177,21 +543,64 @@ impl<T> BatchResult<T> {
     Func: FnMut(Item) -> Result<(), Err>,
     Err: Display + Debug + Send + Sync + 'static,
   {
-    let mut result = BatchResult {
-      count: 0,
-      value: (),
-      errors: ErrorGroup::new(Some("ForEach loop result".to_string())),
-    };
-
+    let mut result = BatchResult::new(()).set_label("ForEach loop result");
     for item in list {
-      result.count += 1;
-      match func(item) {
-        Ok(_) => (),
-        Err(err) => result.append(err),
+      result.record(func(item));
+    }
+    result
+  }
+
+  /// Like foreach, but stops early once the error budget is spent
+  pub fn foreach_with_budget<Item, Err, Func>(
+    list: impl Iterator<Item = Item>,
+    budget: &ErrorBudget<Err>,
+    func: &mut Func,
+  ) -> BatchResult<()>
+  where
+    Func: FnMut(Item) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    let mut result = BatchResult::new(()).set_label("ForEach loop result");
+    let mut list = list;
+    for item in &mut list {
+      if result.step_budget(func(item), budget) {
+        result.abort_remaining(&list);
+        break;
       }
     }
     result
   }
+
+  /// Record a step and check it against the budget, returning true if the budget is now spent
+  fn step_budget<E: Debug>(&mut self, result: Result<(), E>, budget: &ErrorBudget<E>) -> bool {
+    let errors = self.errors.len() as u64 + result.is_err() as u64;
+    let reason = budget.check(self.count + 1, errors, result.as_ref().err());
+    self.record(result);
+
+    if let Some(reason) = reason {
+      self.aborted = Some(Aborted {
+        reason,
+        processed: self.count,
+        remaining: None,
+      });
+    }
+    self.aborted.is_some()
+  }
+
+  /// Note how many items were skipped after aborting, without running them
+  fn abort_remaining<I: Iterator>(&mut self, rest: &I) {
+    if let Some(aborted) = &mut self.aborted {
+      aborted.remaining = remaining(rest);
+    }
+  }
+
+  /// Count a single step, keeping the error if it failed
+  pub(crate) fn record<E: Debug>(&mut self, result: Result<(), E>) {
+    self.count += 1;
+    if let Err(err) = result {
+      self.append(err);
+    }
+  }
 }
 
 impl<T> Grouper for BatchResult<T> {
@@ -199,55 +608,106 @@ impl<T> Grouper for BatchResult<T> {
 
   fn context(self, ctx: String) -> BatchResult<T> {
     BatchResult {
-      errors: self.errors.set_label(ctx),
+      errors: self.errors.context(ctx),
       ..self
     }
   }
 
+  fn with_context<F>(self, func: F) -> BatchResult<T>
+  where
+    F: FnOnce() -> String,
+  {
+    match self.is_ok() {
+      true => self,
+      false => self.context(func()),
+    }
+  }
+
   /// Convert this to a result, Ok(values) if errors is None and Err(errors) if not
   fn as_result<E: From<ErrorGroup>>(self) -> Result<Self::Result, E> {
-    match self.errors.len() {
-      0 => Ok(self.value),
-      _ => Err(self.errors.into()),
+    match self.is_ok() {
+      true => Ok(self.value),
+      false => Err(self.all_errors().into()),
     }
   }
 }
 
-/*
+/// Trying an action on a batch runs it against the value, counting it and recording any errors
+///
+/// The outcome is still returned, so the caller can react to a failure as well as report it.
+#[cfg(feature = "try_mut")]
+impl<T: TryMut> TryMut for BatchResult<T> {
+  type Error = T::Error;
+  type Action = T::Action;
 
-----  Found a nightly workaround for try_insert for hashmap, so I'm going to wait to implement
-      this
+  fn try_mut(&mut self, action: &mut Self::Action) -> PoisonedErr<Self::Error> {
+    self.count += 1;
+    let res = self.value.try_mut(action);
+    match &res {
+      PoisonedErr::Ok => (),
+      PoisonedErr::Err(err) => self.errors.push(format!("{:#}", err)),
+      PoisonedErr::Poisoned(err, revert_err) => {
+        self.errors.push(format!("{:#}", err));
+        self
+          .fatal
+          .get_or_insert_with(|| ErrorGroup::new(Some("Poisoned Reverts".to_string())))
+          .push(format!(
+            "{:#} - the revert failed with: {:#}",
+            err, revert_err
+          ));
+      }
+    }
+    res
+  }
+}
+
+/// A report of the whole batch, with each child indented under its parent
+impl<T> Display for BatchResult<T> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    self.write_report(f, self.errors.label().unwrap_or("Batch"), 0)
+  }
+}
 
-/// Attempt to fold a set of values into the accumulator, rolling back errors
+/// Using ? on a BatchResult returns the accumulated value, or throws the batch when it has errors
 ///
-/// This is heavy, and requires implementing TryMut to use this. The difference is that the
-/// accumulator won't be left in an unknown state after an error.
-#[cfg(feature = "try_mut")]
-pub fn try_fold<U, F>(
-  accumulator: T,
-  list: impl Iterator<Item = U>,
-  action: Box<dyn Fn(U) -> T::Action>,
-) -> BatchResult<T>
-where
-  F: Fn(&mut T, &U) -> PatchResult<T::Error, T::Action>,
-  T: Revertable,
-{
-  let acc = BatchResult::new(accumulator);
-  // for item in list {
-  //   acc.count += 1;
-  //   match acc.value.try_mut(|value| func(value, &item)) {
-  //     PoisonErr::Ok => (),
-  //     PoisonErr::Err(err) => acc.append(err),
-  //     PoisonErr::Poisoned(err1, err2) => {
-  //       let error = anyhow!(err2).context(err1);
-  //       println!("{}", error);
-  //     }
-  //   };
-  // }
-  acc
+/// The thrown batch is kept whole, so no accumulator has to be made up for it.
+#[cfg(feature = "try_trait")]
+impl<T> std::ops::Try for BatchResult<T> {
+  type Output = T;
+  type Residual = BatchResult<T>;
+
+  fn from_output(value: Self::Output) -> Self {
+    BatchResult::new(value)
+  }
+
+  fn branch(self) -> std::ops::ControlFlow<Self::Residual, Self::Output> {
+    match self.is_ok() {
+      true => std::ops::ControlFlow::Continue(self.value),
+      false => std::ops::ControlFlow::Break(self),
+    }
+  }
+}
+
+#[cfg(feature = "try_trait")]
+impl<T> std::ops::Residual<T> for BatchResult<T> {
+  type TryType = BatchResult<T>;
 }
 
-// A set of functions where all need to succeed or the successes should also be rolled back.
-// THINK: Maybe a macro
-// pub fn transaction()
-*/
+/// A failed batch thrown into a function returning the same kind of batch is passed on as is,
+/// keeping its counts
+#[cfg(feature = "try_trait")]
+impl<T> std::ops::FromResidual<BatchResult<T>> for BatchResult<T> {
+  fn from_residual(residual: BatchResult<T>) -> Self {
+    residual
+  }
+}
+
+/// A failed batch thrown into a function returning a Result becomes its errors
+///
+/// Only the errors are kept, so the counts of the batch are lost.
+#[cfg(feature = "try_trait")]
+impl<T, U, E: From<ErrorGroup>> std::ops::FromResidual<BatchResult<T>> for Result<U, E> {
+  fn from_residual(residual: BatchResult<T>) -> Self {
+    Err(residual.all_errors().into())
+  }
+}
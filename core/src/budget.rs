@@ -0,0 +1,167 @@
+//! Limits on how many errors to accept before giving up on a run
+//!
+//! Accumulating errors is only useful up to a point. If a large share of the rows in a file fail,
+//! it is most likely the wrong format and processing the rest is wasted work. A budget describes
+//! when to stop, and the result records that it was aborted and how much was left.
+
+use crate::local::*;
+
+/// Flags the errors that should stop a run immediately
+type StopOn<E> = Box<dyn Fn(&E) -> bool + Send + Sync>;
+
+/// The conditions for stopping a run early
+///
+/// Each limit is optional, and the run stops as soon as any of the ones set is broken.
+///
+/// Example:
+/// ```rust
+/// use allwhat::{ErrorBudget, SplitResult};
+///
+/// // Give up once more than a quarter of the rows fail, but only after seeing the first 20
+/// let budget = ErrorBudget::new().max_ratio(0.25, 20);
+///
+/// let rows = (0..1000).map(|i| match i % 2 {
+///   0 => Ok(i),
+///   _ => Err(format!("Row {} is the wrong format", i)),
+/// });
+/// let split = SplitResult::map_with_budget(rows, &budget, |row| row);
+///
+/// let aborted = split.aborted().unwrap();
+/// assert_eq!(aborted.processed, 20);
+/// assert_eq!(aborted.remaining, Some(980));
+/// ```
+pub struct ErrorBudget<E> {
+  max_errors: Option<u64>,
+  max_ratio: Option<(f64, u64)>,
+  stop_on: Option<StopOn<E>>,
+}
+
+impl<E> Default for ErrorBudget<E> {
+  fn default() -> ErrorBudget<E> {
+    ErrorBudget {
+      max_errors: None,
+      max_ratio: None,
+      stop_on: None,
+    }
+  }
+}
+
+impl<E> Debug for ErrorBudget<E> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.debug_struct("ErrorBudget")
+      .field("max_errors", &self.max_errors)
+      .field("max_ratio", &self.max_ratio)
+      .field("stop_on", &self.stop_on.as_ref().map(|_| "Fn(&E) -> bool"))
+      .finish()
+  }
+}
+
+impl<E> ErrorBudget<E> {
+  /// A budget with no limits, which never stops early
+  pub fn new() -> ErrorBudget<E> {
+    Default::default()
+  }
+
+  /// Stop once this many errors have been found
+  pub fn max_errors(self, count: u64) -> ErrorBudget<E> {
+    ErrorBudget {
+      max_errors: Some(count),
+      ..self
+    }
+  }
+
+  /// Stop once the share of failed items goes above the ratio
+  ///
+  /// The ratio is only checked after the first warm_up items, since a single early error would
+  /// otherwise be 100% of the run.
+  pub fn max_ratio(self, ratio: f64, warm_up: u64) -> ErrorBudget<E> {
+    ErrorBudget {
+      max_ratio: Some((ratio, warm_up)),
+      ..self
+    }
+  }
+
+  /// Stop on the first error that the function flags, such as one of a fatal class
+  pub fn stop_on<F>(self, func: F) -> ErrorBudget<E>
+  where
+    F: Fn(&E) -> bool + Send + Sync + 'static,
+  {
+    ErrorBudget {
+      stop_on: Some(Box::new(func)),
+      ..self
+    }
+  }
+
+  /// Check the budget after an item has been processed, returning the reason if it is spent
+  pub(crate) fn check(&self, processed: u64, errors: u64, latest: Option<&E>) -> Option<String> {
+    if let (Some(func), Some(err)) = (&self.stop_on, latest) {
+      if func(err) {
+        return Some("Found an error flagged to stop on".to_string());
+      }
+    }
+
+    if let Some(max) = self.max_errors {
+      if errors >= max {
+        return Some(format!("Reached the limit of {} errors", max));
+      }
+    }
+
+    match self.max_ratio {
+      Some((ratio, warm_up)) if processed >= warm_up && processed > 0 => {
+        let actual = errors as f64 / processed as f64;
+        match actual > ratio {
+          true => Some(format!(
+            "{} of {} items failed, over the limit of {:.1}%",
+            errors,
+            processed,
+            ratio * 100.0
+          )),
+          false => None,
+        }
+      }
+      _ => None,
+    }
+  }
+}
+
+/// A record of a run that was stopped for breaking its error budget
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serializable", derive(serde::Serialize))]
+pub struct Aborted {
+  /// Which limit was broken
+  pub reason: String,
+
+  /// The number of items processed, including the one that broke the budget
+  pub processed: u64,
+
+  /// The number of items that were never processed, if the input could tell without running them
+  pub remaining: Option<u64>,
+}
+
+/// The number of items left in an iterator, if it knows without being run
+///
+/// Counting the rest by iterating would run the work the budget is there to skip, so only an exact
+/// size hint is trusted.
+pub(crate) fn remaining<I: Iterator>(rest: &I) -> Option<u64> {
+  match rest.size_hint() {
+    (lower, Some(upper)) if lower == upper => Some(lower as u64),
+    _ => None,
+  }
+}
+
+impl Display for Aborted {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    match self.remaining {
+      Some(remaining) => write!(
+        f,
+        "Aborted after {} items with {} left unprocessed: {}",
+        self.processed, remaining, self.reason
+      ),
+      None => write!(
+        f,
+        "Aborted after {} items with the rest left unprocessed: {}",
+        self.processed, self.reason
+      ),
+    }
+  }
+}
@@ -0,0 +1,145 @@
+//! Commit a list in chunks, isolating the bad items from failed chunks
+//!
+//! Bulk inserts are much faster in chunks, but one bad row fails the whole chunk. Rather than
+//! throwing away the good rows with it, a failed chunk is split in half and each half retried,
+//! narrowing down until the bad rows are found and everything else has been committed.
+//!
+//! This assumes a failed commit leaves nothing behind, such as a database transaction rolling back.
+
+use crate::local::*;
+
+/// The outcome of committing a list in chunks
+#[derive(Debug)]
+pub struct ChunkResult<T> {
+  items: SplitResult<T>,
+  chunks: BatchResult<()>,
+}
+
+impl<T> ChunkResult<T> {
+  /// The committed items as values, and an error for each item that failed on its own
+  pub fn items(&self) -> &SplitResult<T> {
+    &self.items
+  }
+
+  /// One step per chunk, holding the errors from the chunks that failed to commit as a whole
+  pub fn chunks(&self) -> &BatchResult<()> {
+    &self.chunks
+  }
+
+  /// Break the result into its item and chunk level results
+  pub fn into_parts(self) -> (SplitResult<T>, BatchResult<()>) {
+    (self.items, self.chunks)
+  }
+
+  /// Whether every chunk committed on the first try
+  pub fn is_ok(&self) -> bool {
+    self.chunks.is_ok()
+  }
+}
+
+impl<T> SplitResult<T> {
+  /// Group the list into chunks of the given size and commit each one
+  ///
+  /// When a chunk fails, its error is kept as a chunk level error and the chunk is bisected to
+  /// find the bad items. Those become item level errors, indexed by their position in the list,
+  /// while the rest are committed in the smaller pieces. A size of zero is treated as one.
+  ///
+  /// Example:
+  /// ```rust
+  /// use allwhat::SplitResult;
+  ///
+  /// let mut database = vec![];
+  /// let result = SplitResult::chunked(0..10, 4, |chunk: &[i32]| {
+  ///   match chunk.contains(&6) {
+  ///     true => Err("Duplicate key 6"),
+  ///     false => Ok(database.extend_from_slice(chunk)),
+  ///   }
+  /// });
+  ///
+  /// assert_eq!(database, vec![0, 1, 2, 3, 4, 5, 7, 8, 9]);
+  /// assert_eq!(result.chunks().count_error(), 1);
+  /// assert_eq!(result.items().failed_indices(), &[6]);
+  /// ```
+  pub fn chunked<E, F>(list: impl Iterator<Item = T>, size: usize, mut commit: F) -> ChunkResult<T>
+  where
+    F: FnMut(&[T]) -> Result<(), E>,
+    E: Display + Debug + Sync + Send + 'static,
+  {
+    let size = size.max(1);
+    let mut result = ChunkResult {
+      items: SplitResult::empty(),
+      chunks: BatchResult::new(()).set_label("Chunk Errors"),
+    };
+
+    let mut chunk = Vec::with_capacity(size);
+    let mut start = 0;
+    for item in list {
+      chunk.push(item);
+      if chunk.len() == size {
+        let full = std::mem::replace(&mut chunk, Vec::with_capacity(size));
+        result.commit_chunk(full, start, &mut commit);
+        start += size;
+      }
+    }
+    if !chunk.is_empty() {
+      result.commit_chunk(chunk, start, &mut commit);
+    }
+    result
+  }
+}
+
+impl<T> ChunkResult<T> {
+  /// Commit a whole chunk, falling back to bisecting it if it fails
+  fn commit_chunk<E, F>(&mut self, chunk: Vec<T>, start: usize, commit: &mut F)
+  where
+    F: FnMut(&[T]) -> Result<(), E>,
+    E: Debug,
+  {
+    match commit(&chunk) {
+      Ok(()) => {
+        self.chunks.record(Ok::<(), E>(()));
+        self.add_committed(chunk, start);
+      }
+      Err(err) => {
+        self.chunks.record(Err::<(), _>(&err));
+        match chunk.len() {
+          1 => self.items.add::<E>(start, Err(err)),
+          _ => self.bisect(chunk, start, commit),
+        }
+      }
+    }
+  }
+
+  /// Split a failed piece in half and try each half on its own
+  fn bisect<E, F>(&mut self, mut front: Vec<T>, start: usize, commit: &mut F)
+  where
+    F: FnMut(&[T]) -> Result<(), E>,
+    E: Debug,
+  {
+    let back = front.split_off(front.len() / 2);
+    let back_start = start + front.len();
+    self.commit_piece(front, start, commit);
+    self.commit_piece(back, back_start, commit);
+  }
+
+  /// Commit part of a failed chunk, recursing until the bad items are on their own
+  fn commit_piece<E, F>(&mut self, piece: Vec<T>, start: usize, commit: &mut F)
+  where
+    F: FnMut(&[T]) -> Result<(), E>,
+    E: Debug,
+  {
+    match commit(&piece) {
+      Ok(()) => self.add_committed(piece, start),
+      Err(err) => match piece.len() {
+        1 => self.items.add::<E>(start, Err(err)),
+        _ => self.bisect(piece, start, commit),
+      },
+    }
+  }
+
+  fn add_committed(&mut self, items: Vec<T>, start: usize) {
+    for (offset, item) in items.into_iter().enumerate() {
+      self.items.add::<()>(start + offset, Ok(item));
+    }
+  }
+}
@@ -54,10 +54,32 @@ impl Debug for AnyhowError {
 pub trait Grouper {
   type Result;
 
-  // Set a label to apply to all the errors
+  /// Add a context to the errors, stacking on top of any earlier ones rather than replacing them
   fn context(self, ctx: String) -> Self;
 
+  /// A lazy version of context, only building the message if there are errors to describe
+  ///
+  /// The default always builds the message, so implementors that can tell when they are clean
+  /// should override it.
+  fn with_context<F>(self, func: F) -> Self
+  where
+    Self: Sized,
+    F: FnOnce() -> String,
+  {
+    self.context(func())
+  }
+
   fn as_result<E: From<ErrorGroup>>(self) -> Result<Self::Result, E>;
+
+  /// Convert to a plain result so the errors can be thrown with ?
+  ///
+  /// This is the stable path. With the nightly "try_trait" feature, ? works on the group directly.
+  fn into_result(self) -> Result<Self::Result, ErrorGroup>
+  where
+    Self: Sized,
+  {
+    self.as_result()
+  }
 }
 
 /// An error accumulator
@@ -65,9 +87,14 @@ pub trait Grouper {
 /// This is intended to enumerate all the errors found in a transaction rather than failing on
 /// the first
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serializable", derive(serde::Serialize))]
 pub struct ErrorGroup {
   label: Option<String>,
+  /// Descriptions of what was being done when the errors occurred, innermost first
+  context: Vec<String>,
   errors: Vec<String>,
+  /// Labelled sub-groups, such as the errors from each stage of a pipeline
+  groups: Vec<ErrorGroup>,
 }
 
 impl std::error::Error for ErrorGroup {}
@@ -81,11 +108,34 @@ impl Display for ErrorGroup {
       .fold(String::new(), |acc, (i, err)| {
         format!("{}\t{}) {}\n", acc, i + 1, err)
       });
-    let label = match &self.label {
-      Some(val) => val.clone(),
-      None => "Error Group".to_string(),
+    // Sub-groups are nested one level deeper
+    let groups = self.groups.iter().fold(String::new(), |acc, group| {
+      group
+        .to_string()
+        .lines()
+        .fold(acc, |acc, line| format!("{}\t{}\n", acc, line))
+    });
+    let label = match (&self.label, self.context.is_empty()) {
+      (Some(val), true) => val.clone(),
+      (None, true) => "Error Group".to_string(),
+      // With a context, show the whole chain from the outermost down to the error count
+      (label, false) => {
+        let count = match self.len() {
+          1 => "1 error".to_string(),
+          len => format!("{} errors", len),
+        };
+        self
+          .context
+          .iter()
+          .rev()
+          .chain(label)
+          .chain(std::iter::once(&count))
+          .map(String::as_str)
+          .collect::<Vec<_>>()
+          .join(" -> ")
+      }
     };
-    write!(f, "{}:\n{}", label, errors)
+    write!(f, "{}:\n{}{}", label, errors, groups)
   }
 }
 
@@ -94,18 +144,46 @@ impl ErrorGroup {
   pub fn new(label: Option<String>) -> ErrorGroup {
     ErrorGroup {
       label,
+      context: vec![],
       errors: vec![],
+      groups: vec![],
     }
   }
 
-  /// Return the number of errors contained
+  /// Return the number of errors contained, including those in sub-groups
   pub fn len(&self) -> usize {
-    self.errors.len()
+    self.errors.len() + self.groups.iter().map(ErrorGroup::len).sum::<usize>()
   }
 
   /// Return if there are any errors in the list
   pub fn is_empty(&self) -> bool {
-    self.errors.is_empty()
+    self.len() == 0
+  }
+
+  /// Iterate over the error messages, with this group's own errors first followed by each
+  /// sub-group's in the order they were added
+  pub fn iter(&self) -> Box<dyn Iterator<Item = &String> + '_> {
+    Box::new(
+      self
+        .errors
+        .iter()
+        .chain(self.groups.iter().flat_map(ErrorGroup::iter)),
+    )
+  }
+
+  /// The label, if one was given
+  pub fn label(&self) -> Option<&str> {
+    self.label.as_deref()
+  }
+
+  /// The labelled sub-groups
+  pub fn groups(&self) -> &[ErrorGroup] {
+    &self.groups
+  }
+
+  /// Nest another group inside this one, keeping its label
+  pub fn push_group(&mut self, group: ErrorGroup) {
+    self.groups.push(group);
   }
 
   pub fn set_label(self, label: String) -> Self {
@@ -115,11 +193,55 @@ impl ErrorGroup {
     }
   }
 
+  /// Wrap the group in another layer of context
+  ///
+  /// Unlike set_label this keeps the earlier contexts, so the display reads from the outermost
+  /// context down: "loading config -> parsing section [db] -> 3 errors"
+  pub fn context(mut self, ctx: String) -> Self {
+    self.context.push(ctx);
+    self
+  }
+
+  /// The stack of contexts, from the innermost to the outermost
+  pub fn contexts(&self) -> &[String] {
+    &self.context
+  }
+
   /// Add a new error to the ErrorGroup in place
   pub fn append<F: Debug>(&mut self, error: F) {
     self.errors.push(format!("{:#?}", error));
   }
 
+  /// Add a message to the group as is, skipping the debug formatting used by append
+  pub fn push<M: Display>(&mut self, msg: M) {
+    self.errors.push(msg.to_string());
+  }
+
+  /// Move all the errors and sub-groups from another group onto the end of this one
+  pub fn merge(&mut self, other: ErrorGroup) {
+    self.errors.extend(other.errors);
+    self.groups.extend(other.groups);
+  }
+
+  /// Add an error of any type, merging it in place if it is already an ErrorGroup
+  ///
+  /// This keeps chained aggregations flat rather than nesting the debug output of one group
+  /// inside another.
+  pub fn absorb<F: Debug + 'static>(&mut self, error: F) {
+    // Taking the group out of the option leaves None behind, so only other errors are appended
+    let mut error = Some(error);
+    let any: &mut dyn std::any::Any = &mut error;
+    if let Some(group) = any
+      .downcast_mut::<Option<ErrorGroup>>()
+      .and_then(Option::take)
+    {
+      self.merge(group);
+    }
+    if let Some(error) = error {
+      self.append(error);
+    }
+  }
+
   /// Add a new error to the ErrorGroup as functional pattern for chaining terms
   pub fn appendf<F: Debug>(mut self, error: F) -> ErrorGroup {
     self.append(format!("{:#?}", error));
@@ -200,8 +322,13 @@ impl IntoIterator for ErrorGroup {
   type Item = String;
   type IntoIter = std::vec::IntoIter<Self::Item>;
 
+  /// Flattens the sub-groups in the same order as iter
   fn into_iter(self) -> Self::IntoIter {
-    self.errors.into_iter()
+    let mut errors = self.errors;
+    for group in self.groups {
+      errors.extend(group);
+    }
+    errors.into_iter()
   }
 }
 
@@ -0,0 +1,28 @@
+//! Conversions between ErrorGroup and the dynamic error types of anyhow and eyre
+//!
+//! ErrorGroup is a std::error::Error, so both crates already wrap it through their blanket From
+//! implementations. These go the other way, downcasting the dynamic error back into the original
+//! group with all its entries and contexts intact. Anything that isn't a group is handed back
+//! unchanged.
+
+use crate::local::*;
+
+use std::convert::TryFrom;
+
+#[cfg(feature = "anyhow")]
+impl TryFrom<anyhow::Error> for ErrorGroup {
+  type Error = anyhow::Error;
+
+  fn try_from(err: anyhow::Error) -> Result<ErrorGroup, anyhow::Error> {
+    err.downcast::<ErrorGroup>()
+  }
+}
+
+#[cfg(feature = "eyre")]
+impl TryFrom<eyre::Report> for ErrorGroup {
+  type Error = eyre::Report;
+
+  fn try_from(report: eyre::Report) -> Result<ErrorGroup, eyre::Report> {
+    report.downcast::<ErrorGroup>()
+  }
+}
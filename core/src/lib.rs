@@ -1,4 +1,5 @@
 //! Tools for playing with specialized results
+#![cfg_attr(feature = "try_trait", feature(try_trait_v2, try_trait_v2_residual))]
 
 // TODO: Is this a concrete error, or a set of traits used by a derive
 // mod base;
@@ -13,14 +14,57 @@ mod split;
 
 mod batch;
 
+mod budget;
+
+mod chunk;
+
+mod metrics;
+
+mod retry;
+
+mod rule;
+
+mod sink;
+
+mod snapshot;
+
+mod validator;
+
+#[cfg(any(feature = "anyhow", feature = "eyre"))]
+mod interop;
+
+#[cfg(feature = "rayon")]
+mod parallel;
+
+#[cfg(feature = "async")]
+mod asynchronous;
+
+#[cfg(feature = "rules")]
+pub mod rules;
+
 #[cfg(feature = "macros")]
 pub use allwhat_macros::*;
 
 /// Export the basics
-pub use crate::{batch::BatchResult, group::ErrorGroup, split::SplitResult};
+pub use crate::{
+  batch::BatchResult,
+  budget::{Aborted, ErrorBudget},
+  chunk::ChunkResult,
+  group::ErrorGroup,
+  metrics::{Clock, Metrics, MetricsSummary, MonotonicClock, StepTiming},
+  retry::{Attempted, Backoff, RetryPolicy, Sleeper, ThreadSleeper},
+  rule::{Rule, RuleOutcome, RuleReport},
+  sink::{ErrorSink, SplitErrors, SplitIter},
+  snapshot::Snapshot,
+  split::SplitResult,
+  validator::{each, field, And, Each, Field, Not, Or, Validator, When},
+};
+
+#[cfg(feature = "async")]
+pub use crate::asynchronous::AsyncRule;
 
-// #[cfg(feature = "try_mut")]
-// mod try_mut;
+#[cfg(feature = "try_mut")]
+pub mod try_mut;
 
 /// Standard items used in components of this crate
 pub(crate) mod local {
@@ -30,12 +74,23 @@ pub(crate) mod local {
 }
 
 pub mod prelude {
-  pub use {super::monadic::Monadic, crate::extract_errors};
+  pub use {
+    super::monadic::{Monadic, Predicate, Required},
+    crate::{extract_errors, require_all},
+  };
 
   // #[cfg(feature = "groups")]
   pub use super::{
     batch::BatchResult,
+    budget::{Aborted, ErrorBudget},
+    chunk::ChunkResult,
     group::{ErrorGroup, Grouper},
+    metrics::{Clock, Metrics, MonotonicClock},
+    retry::{Backoff, RetryPolicy},
+    rule::{Rule, RuleOutcome, RuleReport},
+    sink::{ErrorSink, SplitErrors, SplitIter},
+    snapshot::Snapshot,
     split::SplitResult,
+    validator::Validator,
   };
 }
@@ -0,0 +1,231 @@
+//! Timing each step of a batch, to find the rule or item that makes a run slow
+//!
+//! Timing is opt in through the *_timed versions of the BatchResult constructors. Time is read
+//! through a Clock, so tests can swap in one that moves by a fixed amount.
+
+use crate::local::*;
+
+use crate::rule::run_in_waves;
+
+use std::time::{Duration, Instant};
+
+/// The number of steps listed in a summary's slowest
+const SLOWEST: usize = 5;
+
+/// The nanoseconds in a second
+const NANOS: u128 = 1_000_000_000;
+
+/// Something that can tell how much time has passed
+pub trait Clock {
+  /// The time since some fixed point, which only has to stay the same for the life of the clock
+  fn now(&self) -> Duration;
+}
+
+/// The default clock, measuring from when it was created
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+  start: Instant,
+}
+
+impl Default for MonotonicClock {
+  fn default() -> MonotonicClock {
+    MonotonicClock {
+      start: Instant::now(),
+    }
+  }
+}
+
+impl Clock for MonotonicClock {
+  fn now(&self) -> Duration {
+    self.start.elapsed()
+  }
+}
+
+/// How long a single step took
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serializable", derive(serde::Serialize))]
+pub struct StepTiming {
+  /// The rule name, or the step's position when it has no name
+  pub label: String,
+  pub duration: Duration,
+}
+
+/// The timings of every step in a run, in the order they were run
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+  steps: Vec<StepTiming>,
+}
+
+impl Metrics {
+  pub fn new() -> Metrics {
+    Metrics::default()
+  }
+
+  /// Run a step, recording how long it took
+  pub fn time<R, F: FnOnce() -> R>(&mut self, clock: &dyn Clock, label: String, step: F) -> R {
+    let start = clock.now();
+    let result = step();
+    self.steps.push(StepTiming {
+      label,
+      duration: clock.now().saturating_sub(start),
+    });
+    result
+  }
+
+  pub fn steps(&self) -> &[StepTiming] {
+    &self.steps
+  }
+
+  /// Add the steps of another run after this one's
+  pub fn merge(&mut self, other: Metrics) {
+    self.steps.extend(other.steps);
+  }
+
+  /// The overall figures for the run
+  pub fn summary(&self) -> MetricsSummary {
+    let mut sorted: Vec<&StepTiming> = self.steps.iter().collect();
+    sorted.sort_by_key(|step| std::cmp::Reverse(step.duration));
+
+    let total = self
+      .steps
+      .iter()
+      .map(|step| step.duration)
+      .sum::<Duration>();
+    let count = self.steps.len();
+    // Nearest rank, counting down from the slowest
+    let p95 = match count {
+      0 => Duration::default(),
+      _ => sorted[count - (count * 95).div_ceil(100)].duration,
+    };
+    MetricsSummary {
+      steps: count as u64,
+      total,
+      mean: match count {
+        0 => Duration::default(),
+        // In nanoseconds, as dividing the Duration itself would cut the count down to a u32
+        _ => {
+          let nanos = total.as_nanos() / count as u128;
+          Duration::new((nanos / NANOS) as u64, (nanos % NANOS) as u32)
+        }
+      },
+      p95,
+      slowest: sorted.into_iter().take(SLOWEST).cloned().collect(),
+    }
+  }
+}
+
+/// Serialized with its summary, so the figures don't have to be worked out again by the reader
+#[cfg(feature = "serializable")]
+impl serde::Serialize for Metrics {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+
+    let mut state = serializer.serialize_struct("Metrics", 2)?;
+    state.serialize_field("steps", &self.steps)?;
+    state.serialize_field("summary", &self.summary())?;
+    state.end()
+  }
+}
+
+/// The overall figures for a timed run
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serializable", derive(serde::Serialize))]
+pub struct MetricsSummary {
+  pub steps: u64,
+  pub total: Duration,
+  pub mean: Duration,
+  /// 95% of the steps took this long or less
+  pub p95: Duration,
+  /// The slowest few steps, slowest first
+  pub slowest: Vec<StepTiming>,
+}
+
+impl Display for MetricsSummary {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    write!(
+      f,
+      "{} steps in {:?} (mean {:?}, p95 {:?})",
+      self.steps, self.total, self.mean, self.p95
+    )
+  }
+}
+
+impl<T> BatchResult<T> {
+  /// Like validate, timing each test
+  pub fn validate_timed<E, Func>(
+    value: T,
+    tests: impl Iterator<Item = Func>,
+    clock: &dyn Clock,
+  ) -> BatchResult<T>
+  where
+    Func: FnOnce(&T) -> Result<(), E>,
+    E: Debug,
+  {
+    let mut metrics = Metrics::new();
+    let mut errors = ErrorGroup::new(None);
+    let mut count = 0;
+    for test in tests {
+      count += 1;
+      if let Err(err) = metrics.time(clock, format!("step {}", count), || test(&value)) {
+        errors.append(format!("{:#?}", err))
+      };
+    }
+
+    BatchResult::from_checks(value, count, errors).with_metrics(metrics)
+  }
+
+  /// Like validate_rules, timing each rule under its name
+  pub fn validate_rules_timed<'a>(
+    value: T,
+    rules: impl IntoIterator<Item = &'a Rule<T>>,
+    clock: &dyn Clock,
+  ) -> BatchResult<T>
+  where
+    T: 'a,
+  {
+    let mut metrics = Metrics::new();
+    let rules: Vec<&Rule<T>> = rules.into_iter().collect();
+    let heads: Vec<_> = rules
+      .iter()
+      .map(|rule| (rule.name(), rule.prerequisites()))
+      .collect();
+    let outcomes = run_in_waves(&heads, |wave| {
+      wave
+        .iter()
+        .map(|&index| {
+          let rule = rules[index];
+          metrics.time(clock, rule.name().to_string(), || rule.check(&value))
+        })
+        .collect()
+    });
+
+    BatchResult::new(value)
+      .with_rules(rules.into_iter().zip(outcomes))
+      .with_metrics(metrics)
+  }
+
+  /// Like fold, timing each item
+  pub fn fold_timed<Item, Err, Func>(
+    accumulator: T,
+    list: impl Iterator<Item = Item>,
+    clock: &dyn Clock,
+    func: Func,
+  ) -> BatchResult<T>
+  where
+    Func: Fn(&mut T, Item) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    let mut metrics = Metrics::new();
+    let mut acc = accumulator;
+    let mut errors = ErrorGroup::new(Some("Batch Errors".to_string()));
+    let mut count = 0;
+    for item in list {
+      count += 1;
+      if let Err(err) = metrics.time(clock, format!("item {}", count), || func(&mut acc, item)) {
+        errors.append(format!("{:#?}", err));
+      }
+    }
+
+    BatchResult::from_checks(acc, count, errors).with_metrics(metrics)
+  }
+}
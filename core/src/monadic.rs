@@ -5,6 +5,11 @@
 
 use core::fmt::{Debug, Display};
 
+use crate::group::ErrorGroup;
+
+/// A borrowed test of a value, used by ensure_all so the checks don't all need the same type
+pub type Predicate<'a, T> = &'a dyn Fn(&T) -> bool;
+
 // Add some monad features to the result
 pub trait Monadic<T, E>
 where
@@ -26,6 +31,37 @@ where
   where
     E: Debug + Display + Send + Sync + 'static,
     F: FnOnce(E) -> Result<T, E>;
+
+  /// Turn an Ok into the given error if the predicate does not hold for its value
+  fn ensure<P>(self, pred: P, err: E) -> Result<T, E>
+  where
+    P: FnOnce(&T) -> bool;
+
+  /// Test the Ok value against every predicate, gathering each failure's message into a group
+  ///
+  /// The checks are borrowed as trait objects so different closures can share one list. If this
+  /// is already an error there is nothing to test, so the error becomes the only entry
+  fn ensure_all<M>(self, checks: &[(Predicate<T>, M)]) -> Result<T, ErrorGroup>
+  where
+    M: Display;
+
+  /// Look at the Ok value without consuming it, such as for logging
+  fn tap<F>(self, func: F) -> Result<T, E>
+  where
+    F: FnOnce(&T);
+
+  /// Look at the error without consuming it
+  fn tap_err<F>(self, func: F) -> Result<T, E>
+  where
+    F: FnOnce(&E);
+
+  /// Run a second check even when this one has failed, merging the errors from both
+  ///
+  /// Errors that are already an ErrorGroup are merged flat, so and_also can be chained
+  fn and_also<U, F, E2>(self, func: F) -> Result<(T, U), ErrorGroup>
+  where
+    F: FnOnce() -> Result<U, E2>,
+    E2: Debug + 'static;
 }
 
 impl<T, E> Monadic<T, E> for Result<T, E>
@@ -53,6 +89,151 @@ where
       Err(err) => func(err),
     }
   }
+
+  fn ensure<P>(self, pred: P, err: E) -> Result<T, E>
+  where
+    P: FnOnce(&T) -> bool,
+  {
+    match self {
+      Ok(value) if pred(&value) => Ok(value),
+      Ok(_) => Err(err),
+      Err(err) => Err(err),
+    }
+  }
+
+  fn ensure_all<M>(self, checks: &[(Predicate<T>, M)]) -> Result<T, ErrorGroup>
+  where
+    M: Display,
+  {
+    let mut group = ErrorGroup::new(None);
+    match self {
+      Ok(value) => {
+        for (pred, msg) in checks {
+          if !pred(&value) {
+            group.push(msg);
+          }
+        }
+        match group.is_empty() {
+          true => Ok(value),
+          false => Err(group),
+        }
+      }
+      Err(err) => {
+        group.absorb(err);
+        Err(group)
+      }
+    }
+  }
+
+  fn tap<F>(self, func: F) -> Result<T, E>
+  where
+    F: FnOnce(&T),
+  {
+    if let Ok(value) = &self {
+      func(value);
+    }
+    self
+  }
+
+  fn tap_err<F>(self, func: F) -> Result<T, E>
+  where
+    F: FnOnce(&E),
+  {
+    if let Err(err) = &self {
+      func(err);
+    }
+    self
+  }
+
+  fn and_also<U, F, E2>(self, func: F) -> Result<(T, U), ErrorGroup>
+  where
+    F: FnOnce() -> Result<U, E2>,
+    E2: Debug + 'static,
+  {
+    match (self, func()) {
+      (Ok(first), Ok(second)) => Ok((first, second)),
+      (first, second) => {
+        let mut group = ErrorGroup::new(None);
+        if let Err(err) = first {
+          group.absorb(err);
+        }
+        if let Err(err) = second {
+          group.absorb(err);
+        }
+        Err(group)
+      }
+    }
+  }
+}
+
+/// Turn missing values into labelled errors
+///
+/// The Option counterpart of Monadic, used when gathering required fields such as from a config
+/// file or form where all the missing ones should be reported at once.
+pub trait Required<T> {
+  /// Convert None into a group containing "field `name` is required"
+  fn required(self, field: &str) -> Result<T, ErrorGroup>;
+
+  /// Pass the value through, adding "field `name` is required" to the group if it is missing
+  fn require(self, field: &str, group: &mut ErrorGroup) -> Option<T>;
+}
+
+impl<T> Required<T> for Option<T> {
+  fn required(self, field: &str) -> Result<T, ErrorGroup> {
+    let mut group = ErrorGroup::new(None);
+    match self.require(field, &mut group) {
+      Some(value) => Ok(value),
+      None => Err(group),
+    }
+  }
+
+  fn require(self, field: &str, group: &mut ErrorGroup) -> Option<T> {
+    if self.is_none() {
+      group.push(format!("field `{}` is required", field));
+    }
+    self
+  }
+}
+
+/// Check a set of optional values, returning a tuple of all of them or a group of every missing one
+///
+/// Each term is either a variable, which uses its own name as the field, or a field name assigned
+/// from an expression.
+///
+/// Example:
+/// ```rust
+/// use allwhat::require_all;
+///
+/// let host: Option<&str> = Some("localhost");
+/// let port: Option<u16> = None;
+/// let user: Option<&str> = None;
+///
+/// let missing = require_all!(host, port, user_name => user).unwrap_err();
+/// assert_eq!(
+///   missing.to_string(),
+///   "Missing Fields:\n\t1) field `port` is required\n\t2) field `user_name` is required\n"
+/// );
+/// ```
+#[macro_export]
+macro_rules! require_all {
+  (@inner $group:ident, $field:ident) => {
+    let $field = $crate::prelude::Required::require($field, stringify!($field), &mut $group);
+  };
+
+  (@inner $group:ident, $field:ident => $val:expr) => {
+    let $field = $crate::prelude::Required::require($val, stringify!($field), &mut $group);
+  };
+
+  ($($field:ident $(=> $val:expr)?),+ $(,)?) => {{
+    let mut __missing = $crate::ErrorGroup::new(Some("Missing Fields".to_string()));
+
+    $( $crate::require_all!(@inner __missing, $field $(=> $val)?); )+
+
+    match __missing.is_empty() {
+      true => Ok(($( $field.unwrap(), )+)),
+      false => Err(__missing),
+    }
+  }};
 }
 
 /// The Kleisli Composition
@@ -145,4 +326,91 @@ mod tests {
     // But the value has been partially increased
     assert_eq!(*value.borrow(), 105);
   }
+
+  #[test]
+  fn test_ensure() {
+    let even: Result<i32, String> = Ok(4);
+    assert_eq!(even.ensure(|x| x % 2 == 0, "Odd".to_string()), Ok(4));
+
+    let odd: Result<i32, String> = Ok(5);
+    assert_eq!(
+      odd.ensure(|x| x % 2 == 0, "Odd".to_string()),
+      Err("Odd".to_string())
+    );
+
+    // Every failed predicate is reported, not just the first
+    let value: Result<i32, String> = Ok(15);
+    let group = value
+      .ensure_all(&[
+        (&|x: &i32| *x < 10, "Too big"),
+        (&|x: &i32| x % 2 == 0, "Odd"),
+        (&|x: &i32| *x > 0, "Not positive"),
+      ])
+      .unwrap_err();
+    assert_eq!(group.len(), 2);
+    assert_eq!(group.to_string(), "Error Group:\n\t1) Too big\n\t2) Odd\n");
+  }
+
+  #[test]
+  fn test_tap() {
+    let mut seen = vec![];
+    let ok: Result<i32, String> = Ok(1);
+    let ok = ok
+      .tap(|x| seen.push(*x))
+      .tap_err(|_| panic!("Not an error"));
+    assert_eq!(ok, Ok(1));
+    assert_eq!(seen, vec![1]);
+
+    let mut logged = None;
+    let err: Result<i32, String> = Err("Failed".to_string());
+    let err = err
+      .tap(|_| panic!("Not ok"))
+      .tap_err(|e| logged = Some(e.clone()));
+    assert_eq!(err, Err("Failed".to_string()));
+    assert_eq!(logged, Some("Failed".to_string()));
+  }
+
+  #[test]
+  fn test_required() {
+    use super::Required;
+
+    assert_eq!(Some(3).required("x").unwrap(), 3);
+    let missing = None::<i32>.required("x").unwrap_err();
+    assert_eq!(
+      missing.to_string(),
+      "Error Group:\n\t1) field `x` is required\n"
+    );
+
+    let name = Some("Dave");
+    let age = Some(42);
+    let (name, age, city) = require_all!(name, age, city => Some("Boston")).unwrap();
+    assert_eq!((name, age, city), ("Dave", 42, "Boston"));
+
+    let name: Option<&str> = None;
+    let age = Some(42);
+    let email: Option<&str> = None;
+    let missing = require_all!(name, age, email).unwrap_err();
+    assert_eq!(missing.len(), 2);
+    assert_eq!(
+      missing.to_string(),
+      "Missing Fields:\n\t1) field `name` is required\n\t2) field `email` is required\n"
+    );
+  }
+
+  #[test]
+  fn test_and_also() {
+    let first: Result<i32, String> = Ok(1);
+    assert_eq!(
+      first.and_also(|| Ok::<_, String>("two")).unwrap(),
+      (1, "two")
+    );
+
+    // The second check still runs after the first failed
+    let first: Result<i32, String> = Err("First".to_string());
+    let group = first
+      .and_also(|| Err::<(), _>("Second"))
+      .and_also(|| Err::<(), _>("Third"))
+      .unwrap_err();
+    assert_eq!(group.len(), 3);
+  }
 }
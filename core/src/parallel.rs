@@ -0,0 +1,89 @@
+//! Parallel versions of the split and batch loops, using rayon
+//!
+//! The work is spread across the rayon thread pool, but the results are gathered back in input
+//! order before being sorted, so the values and errors come out exactly as the sequential versions
+//! would produce them.
+
+use crate::local::*;
+
+use crate::rule::run_in_waves;
+
+use rayon::prelude::*;
+
+impl<T: Send> SplitResult<T> {
+  /// A parallel SplitResult::map
+  ///
+  /// Takes anything rayon can iterate in parallel, such as a Vec, a range, or `slice.par_iter()`
+  pub fn par_map<U, E, F>(list: impl IntoParallelIterator<Item = U>, func: F) -> SplitResult<T>
+  where
+    F: Fn(U) -> Result<T, E> + Sync + Send,
+    E: Display + Debug + Sync + Send + 'static,
+  {
+    let results: Vec<Result<T, E>> = list.into_par_iter().map(func).collect();
+
+    let mut split = SplitResult::empty();
+    for (index, result) in results.into_iter().enumerate() {
+      split.add(index, result);
+    }
+    split
+  }
+}
+
+impl<T: Sync> BatchResult<T> {
+  /// A parallel BatchResult::validate, running every test at once against a shared reference
+  ///
+  /// The errors are still reported in the order the tests were given.
+  pub fn par_validate<E, Func>(
+    value: T,
+    tests: impl IntoParallelIterator<Item = Func>,
+  ) -> BatchResult<T>
+  where
+    Func: FnOnce(&T) -> Result<(), E> + Send,
+    E: Debug + Send,
+  {
+    let results: Vec<Result<(), E>> = tests.into_par_iter().map(|test| test(&value)).collect();
+
+    let mut errors = ErrorGroup::new(None);
+    for err in results.iter().filter_map(|result| result.as_ref().err()) {
+      errors.append(format!("{:#?}", err));
+    }
+    BatchResult::from_checks(value, results.len() as u64, errors)
+  }
+
+  /// A parallel BatchResult::validate_rules, keeping the outcome of each rule in the order given
+  ///
+  /// Rules only wait on their own prerequisites, so each wave of ready rules runs at once.
+  pub fn par_validate_rules(value: T, rules: &[Rule<T>]) -> BatchResult<T> {
+    let heads: Vec<_> = rules
+      .iter()
+      .map(|rule| (rule.name(), rule.prerequisites()))
+      .collect();
+    let outcomes = run_in_waves(&heads, |wave| {
+      wave
+        .par_iter()
+        .map(|&index| rules[index].check(&value))
+        .collect()
+    });
+    BatchResult::new(value).with_rules(rules.iter().zip(outcomes))
+  }
+}
+
+impl BatchResult<()> {
+  /// A parallel BatchResult::foreach
+  pub fn par_foreach<Item, Err, Func>(
+    list: impl IntoParallelIterator<Item = Item>,
+    func: &Func,
+  ) -> BatchResult<()>
+  where
+    Func: Fn(Item) -> Result<(), Err> + Sync + Send,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    let results: Vec<Result<(), Err>> = list.into_par_iter().map(func).collect();
+
+    let mut batch = BatchResult::new(()).set_label("ForEach loop result");
+    for result in results {
+      batch.record(result);
+    }
+    batch
+  }
+}
@@ -0,0 +1,238 @@
+//! Retry transient failures before counting them as errors
+//!
+//! Lock contention or a flaky local service can fail an item that would succeed a moment later.
+//! A retry policy gives each item a number of attempts, waiting between them, and only the errors
+//! it deems retryable are tried again. Waiting goes through a Sleeper, so tests can swap in one
+//! that returns immediately.
+
+use crate::local::*;
+
+use std::time::Duration;
+
+/// Something that can wait between attempts
+pub trait Sleeper {
+  fn sleep(&self, duration: Duration);
+}
+
+/// The default sleeper, which blocks the current thread
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadSleeper;
+
+impl Sleeper for ThreadSleeper {
+  fn sleep(&self, duration: Duration) {
+    std::thread::sleep(duration)
+  }
+}
+
+/// How long to wait before each retry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backoff {
+  /// Retry immediately
+  None,
+
+  /// Wait the same amount before every retry
+  Fixed(Duration),
+
+  /// Start with the initial wait, multiplying it by the factor on each retry up to the max
+  Exponential {
+    initial: Duration,
+    factor: u32,
+    max: Duration,
+  },
+}
+
+impl Backoff {
+  /// The wait before the given retry, counting from 1
+  pub fn delay(&self, retry: u32) -> Duration {
+    match *self {
+      Backoff::None => Duration::from_secs(0),
+      Backoff::Fixed(delay) => delay,
+      Backoff::Exponential {
+        initial,
+        factor,
+        max,
+      } => factor
+        .checked_pow(retry.saturating_sub(1))
+        .and_then(|scale| initial.checked_mul(scale))
+        .map_or(max, |delay| delay.min(max)),
+    }
+  }
+}
+
+/// Decides whether an error is worth another attempt
+type RetryIf<E> = Box<dyn Fn(&E) -> bool>;
+
+/// How many times to try an item, how long to wait between tries, and which errors to retry
+///
+/// Example:
+/// ```rust
+/// use allwhat::{Backoff, RetryPolicy, SplitResult};
+/// use std::{cell::Cell, time::Duration};
+///
+/// // Every other call fails with a lock timeout
+/// let calls = Cell::new(0);
+/// let policy = RetryPolicy::new(3)
+///   .backoff(Backoff::Fixed(Duration::from_millis(1)))
+///   .retry_if(|err: &String| err.contains("timeout"));
+///
+/// let split = SplitResult::map_with_retry(0..4, &policy, |x| {
+///   calls.set(calls.get() + 1);
+///   match calls.get() % 2 {
+///     0 => Err("lock timeout".to_string()),
+///     _ => Ok(x * 10),
+///   }
+/// });
+/// assert_eq!(split.values(), &[0, 10, 20, 30]);
+/// assert_eq!(calls.get(), 7);
+/// ```
+pub struct RetryPolicy<E> {
+  max_attempts: u32,
+  backoff: Backoff,
+  retry_if: Option<RetryIf<E>>,
+  sleeper: Box<dyn Sleeper>,
+}
+
+impl<E> Debug for RetryPolicy<E> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.debug_struct("RetryPolicy")
+      .field("max_attempts", &self.max_attempts)
+      .field("backoff", &self.backoff)
+      .field(
+        "retry_if",
+        &self.retry_if.as_ref().map(|_| "Fn(&E) -> bool"),
+      )
+      .finish()
+  }
+}
+
+impl<E> RetryPolicy<E> {
+  /// Try each item up to max_attempts times in total, retrying every error without waiting
+  pub fn new(max_attempts: u32) -> RetryPolicy<E> {
+    RetryPolicy {
+      max_attempts: max_attempts.max(1),
+      backoff: Backoff::None,
+      retry_if: None,
+      sleeper: Box::new(ThreadSleeper),
+    }
+  }
+
+  /// Set how long to wait before each retry
+  pub fn backoff(self, backoff: Backoff) -> RetryPolicy<E> {
+    RetryPolicy { backoff, ..self }
+  }
+
+  /// Only retry the errors that the function accepts, failing the rest on the first attempt
+  pub fn retry_if<F>(self, func: F) -> RetryPolicy<E>
+  where
+    F: Fn(&E) -> bool + 'static,
+  {
+    RetryPolicy {
+      retry_if: Some(Box::new(func)),
+      ..self
+    }
+  }
+
+  /// Replace the thread sleeper, such as with one that records the waits in a test
+  pub fn sleeper<S>(self, sleeper: S) -> RetryPolicy<E>
+  where
+    S: Sleeper + 'static,
+  {
+    RetryPolicy {
+      sleeper: Box::new(sleeper),
+      ..self
+    }
+  }
+
+  /// Run the function until it succeeds, fails with an error not worth retrying, or runs out of
+  /// attempts
+  pub(crate) fn run<T, F>(&self, mut func: F) -> Result<T, Attempted<E>>
+  where
+    F: FnMut() -> Result<T, E>,
+  {
+    let mut attempts = 0;
+    loop {
+      attempts += 1;
+      match func() {
+        Ok(value) => return Ok(value),
+        Err(error) => {
+          let retryable = match &self.retry_if {
+            Some(func) => func(&error),
+            None => true,
+          };
+          if !retryable || attempts >= self.max_attempts {
+            return Err(Attempted { attempts, error });
+          }
+          self.sleeper.sleep(self.backoff.delay(attempts));
+        }
+      }
+    }
+  }
+}
+
+/// The final error of an item, along with how many attempts were made before giving up
+pub struct Attempted<E> {
+  pub attempts: u32,
+  pub error: E,
+}
+
+impl<E: Debug> Debug for Attempted<E> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    match self.attempts {
+      1 => write!(f, "Failed after 1 attempt: ")?,
+      attempts => write!(f, "Failed after {} attempts: ", attempts)?,
+    }
+    Debug::fmt(&self.error, f)
+  }
+}
+
+impl<E: Display> Display for Attempted<E> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    match self.attempts {
+      1 => write!(f, "Failed after 1 attempt: {}", self.error),
+      attempts => write!(f, "Failed after {} attempts: {}", attempts, self.error),
+    }
+  }
+}
+
+impl<T> SplitResult<T> {
+  /// Like map, but retries each failing element according to the policy
+  ///
+  /// The function borrows each element so it can be run more than once. Each error in the result
+  /// reports how many attempts its element took.
+  pub fn map_with_retry<U, E, F>(
+    list: impl Iterator<Item = U>,
+    policy: &RetryPolicy<E>,
+    func: F,
+  ) -> SplitResult<T>
+  where
+    F: Fn(&U) -> Result<T, E>,
+    E: Display + Debug + Sync + Send + 'static,
+  {
+    let mut result = SplitResult::empty();
+    for (index, item) in list.enumerate() {
+      result.add(index, policy.run(|| func(&item)));
+    }
+    result
+  }
+}
+
+impl BatchResult<()> {
+  /// Like foreach, but retries each failing item according to the policy
+  ///
+  /// Each error in the result reports how many attempts its item took.
+  pub fn foreach_with_retry<Item, Err, Func>(
+    list: impl Iterator<Item = Item>,
+    policy: &RetryPolicy<Err>,
+    func: &mut Func,
+  ) -> BatchResult<()>
+  where
+    Func: FnMut(&Item) -> Result<(), Err>,
+    Err: Display + Debug + Send + Sync + 'static,
+  {
+    let mut result = BatchResult::new(()).set_label("ForEach loop result");
+    for item in list {
+      result.record(policy.run(|| func(&item)));
+    }
+    result
+  }
+}
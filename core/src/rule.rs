@@ -0,0 +1,325 @@
+//! Named validation rules, so a report can say which checks passed and which failed
+//!
+//! An anonymous closure can only say what went wrong. A rule also carries a name and an optional
+//! description, and BatchResult::validate_rules records the outcome of each one so it can be looked
+//! up afterwards.
+
+use crate::local::*;
+
+/// The check run by a rule, with the error already turned into a message
+type Check<T> = Box<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+
+/// A named check against a value
+///
+/// Example:
+/// ```rust
+/// use allwhat::prelude::*;
+///
+/// let rules = vec![
+///   Rule::new("positive", |x: &i32| match *x > 0 {
+///     true => Ok(()),
+///     false => Err(format!("{} is not positive", x)),
+///   }),
+///   Rule::new("even", |x: &i32| match x % 2 {
+///     0 => Ok(()),
+///     _ => Err(format!("{} is odd", x)),
+///   })
+///   .describe("Only even values can be split in half"),
+/// ];
+///
+/// let batch = BatchResult::validate_rules(7, &rules);
+/// assert_eq!(batch.rule_outcome("positive"), Some(&RuleOutcome::Passed));
+/// assert_eq!(
+///   batch.rule_outcome("even"),
+///   Some(&RuleOutcome::Failed("7 is odd".to_string()))
+/// );
+/// ```
+pub struct Rule<T> {
+  name: String,
+  description: Option<String>,
+  /// The names of the rules that have to pass before this one is worth running
+  requires: Vec<String>,
+  check: Check<T>,
+}
+
+impl<T> Debug for Rule<T> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.debug_struct("Rule")
+      .field("name", &self.name)
+      .field("description", &self.description)
+      .field("requires", &self.requires)
+      .finish()
+  }
+}
+
+impl<T> Rule<T> {
+  /// Create a rule from a name and the check to run
+  pub fn new<E, F>(name: &str, check: F) -> Rule<T>
+  where
+    F: Fn(&T) -> Result<(), E> + Send + Sync + 'static,
+    E: Display,
+  {
+    Rule {
+      name: name.to_string(),
+      description: None,
+      requires: vec![],
+      check: Box::new(move |value| check(value).map_err(|err| err.to_string())),
+    }
+  }
+
+  /// Explain what the rule is for, which is kept alongside its outcome
+  pub fn describe(self, description: &str) -> Rule<T> {
+    Rule {
+      description: Some(description.to_string()),
+      ..self
+    }
+  }
+
+  /// Only run this rule if the named rule passes, otherwise record it as skipped
+  ///
+  /// This keeps one root failure, such as a value that isn't valid JSON, from being followed by a
+  /// pile of rules that could never have passed. It can be called more than once to require
+  /// several rules. Requiring a rule that isn't being run, or rules that end up requiring this
+  /// one, fails the rule.
+  pub fn requires(mut self, name: &str) -> Rule<T> {
+    self.requires.push(name.to_string());
+    self
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn description(&self) -> Option<&str> {
+    self.description.as_deref()
+  }
+
+  /// The names of the rules this one requires
+  pub fn prerequisites(&self) -> &[String] {
+    &self.requires
+  }
+
+  /// Run the check against a value, ignoring any prerequisites
+  pub fn check(&self, value: &T) -> RuleOutcome {
+    match (self.check)(value) {
+      Ok(()) => RuleOutcome::Passed,
+      Err(msg) => RuleOutcome::Failed(msg),
+    }
+  }
+
+  /// Pair an outcome of this rule with its name and description
+  pub(crate) fn report(&self, outcome: RuleOutcome) -> RuleReport {
+    RuleReport {
+      name: self.name.clone(),
+      description: self.description.clone(),
+      outcome,
+    }
+  }
+}
+
+/// What happened when a rule was run
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serializable", derive(serde::Serialize))]
+pub enum RuleOutcome {
+  Passed,
+  /// The rule failed with this message
+  Failed(String),
+  /// The rule wasn't run because these prerequisites didn't pass
+  Skipped(Vec<String>),
+}
+
+impl RuleOutcome {
+  pub fn is_passed(&self) -> bool {
+    matches!(self, RuleOutcome::Passed)
+  }
+
+  pub fn is_failed(&self) -> bool {
+    matches!(self, RuleOutcome::Failed(_))
+  }
+
+  pub fn is_skipped(&self) -> bool {
+    matches!(self, RuleOutcome::Skipped(_))
+  }
+}
+
+/// The outcome of a single rule in a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serializable", derive(serde::Serialize))]
+pub struct RuleReport {
+  pub name: String,
+  pub description: Option<String>,
+  pub outcome: RuleOutcome,
+}
+
+impl Display for RuleReport {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    match &self.outcome {
+      RuleOutcome::Passed => write!(f, "{}: passed", self.name),
+      RuleOutcome::Failed(msg) => write!(f, "{}: failed - {}", self.name, msg),
+      RuleOutcome::Skipped(unmet) => {
+        write!(f, "{}: skipped - requires {}", self.name, unmet.join(", "))
+      }
+    }
+  }
+}
+
+/// The name and prerequisites of each rule, which is all that's needed to order them
+pub(crate) type Heads<'a> = [(&'a str, &'a [String])];
+
+/// Work out the outcome of every rule, running them in waves so prerequisites are decided first
+///
+/// Each wave holds the rules whose prerequisites have all passed, and run_wave returns their
+/// outcomes in the same order. Within a wave the rules are independent, so they can be run in
+/// parallel. The outcomes come back in the order the rules were given.
+pub(crate) fn run_in_waves<F>(heads: &Heads, mut run_wave: F) -> Vec<RuleOutcome>
+where
+  F: FnMut(&[usize]) -> Vec<RuleOutcome>,
+{
+  let mut waves = Waves::new(heads);
+  while let Some(wave) = waves.next_ready() {
+    let results = run_wave(&wave);
+    waves.record(&wave, results);
+  }
+  waves.into_outcomes()
+}
+
+/// The state of run_in_waves, for callers that can't run a wave from a closure, such as async ones
+pub(crate) struct Waves<'h, 'a> {
+  heads: &'h Heads<'a>,
+  outcomes: Vec<Option<RuleOutcome>>,
+}
+
+impl<'h, 'a> Waves<'h, 'a> {
+  pub(crate) fn new(heads: &'h Heads<'a>) -> Waves<'h, 'a> {
+    Waves {
+      heads,
+      outcomes: vec![None; heads.len()],
+    }
+  }
+
+  /// The rules to run next, or None once every rule has an outcome
+  pub(crate) fn next_ready(&mut self) -> Option<Vec<usize>> {
+    let wave = next_wave(self.heads, &mut self.outcomes);
+    match wave.is_empty() {
+      true => None,
+      false => Some(wave),
+    }
+  }
+
+  /// Keep the outcomes of a wave, given in the same order as its rules
+  pub(crate) fn record(&mut self, wave: &[usize], results: Vec<RuleOutcome>) {
+    for (index, outcome) in wave.iter().zip(results) {
+      self.outcomes[*index] = Some(outcome);
+    }
+  }
+
+  /// The outcome of every rule, in the order the rules were given
+  pub(crate) fn into_outcomes(self) -> Vec<RuleOutcome> {
+    self.outcomes.into_iter().flatten().collect()
+  }
+}
+
+/// Find the rules that are ready to run, deciding any that can't ever run
+///
+/// A rule is skipped when a prerequisite failed or was skipped. Naming a rule that isn't there,
+/// or depending on rules in a cycle, is a mistake in the rules rather than the value, so those
+/// rules fail instead of quietly letting the value through. Once nothing is ready the wave comes
+/// back empty.
+pub(crate) fn next_wave(heads: &Heads, outcomes: &mut [Option<RuleOutcome>]) -> Vec<usize> {
+  loop {
+    let mut ready = vec![];
+    let mut changed = false;
+    for (index, (_, requires)) in heads.iter().enumerate() {
+      if outcomes[index].is_some() {
+        continue;
+      }
+      let mut unknown = vec![];
+      let mut unmet = vec![];
+      let mut waiting = false;
+      for name in requires.iter() {
+        match heads.iter().position(|(other, _)| other == name) {
+          Some(other) => match &outcomes[other] {
+            Some(RuleOutcome::Passed) => (),
+            Some(_) => unmet.push(name.clone()),
+            None => waiting = true,
+          },
+          None => unknown.push(name.as_str()),
+        }
+      }
+      let outcome = match (unknown.is_empty(), unmet.is_empty(), waiting) {
+        (false, _, _) => Some(RuleOutcome::Failed(format!(
+          "requires unknown rules {}",
+          unknown.join(", ")
+        ))),
+        (true, false, _) => Some(RuleOutcome::Skipped(unmet)),
+        (true, true, false) => {
+          ready.push(index);
+          None
+        }
+        (true, true, true) => None,
+      };
+      if outcome.is_some() {
+        outcomes[index] = outcome;
+        changed = true;
+      }
+    }
+
+    match (ready.is_empty(), changed) {
+      (false, _) => return ready,
+      (true, true) => continue,
+      (true, false) => {
+        // Everything left is waiting on a rule that is still waiting, so there is a cycle. The
+        // rules on it fail, then the next pass skips the rules that were waiting on them.
+        let undecided: Vec<usize> = (0..heads.len())
+          .filter(|&index| outcomes[index].is_none())
+          .collect();
+        if undecided.is_empty() {
+          return ready;
+        }
+        let cycle: Vec<(usize, String)> = undecided
+          .into_iter()
+          .filter(|&index| requires_itself(heads, outcomes, index))
+          .map(|index| {
+            let waiting: Vec<&str> = heads[index]
+              .1
+              .iter()
+              .filter(
+                |name| match heads.iter().position(|(other, _)| other == *name) {
+                  Some(other) => outcomes[other].is_none(),
+                  None => false,
+                },
+              )
+              .map(|name| name.as_str())
+              .collect();
+            (index, waiting.join(", "))
+          })
+          .collect();
+        for (index, waiting) in cycle {
+          outcomes[index] = Some(RuleOutcome::Failed(format!(
+            "requires rules that form a cycle: {}",
+            waiting
+          )));
+        }
+      }
+    }
+  }
+}
+
+/// Whether a rule can be reached again by following the prerequisites that are still undecided
+fn requires_itself(heads: &Heads, outcomes: &[Option<RuleOutcome>], start: usize) -> bool {
+  let mut seen = vec![false; heads.len()];
+  let mut todo = vec![start];
+  while let Some(index) = todo.pop() {
+    let requires = heads[index].1.iter();
+    for next in requires.filter_map(|name| heads.iter().position(|(other, _)| other == name)) {
+      if next == start {
+        return true;
+      }
+      if outcomes[next].is_none() && !seen[next] {
+        seen[next] = true;
+        todo.push(next);
+      }
+    }
+  }
+  false
+}
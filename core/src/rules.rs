@@ -0,0 +1,154 @@
+//! Ready-made rules for the checks that every project ends up writing
+//!
+//! Each constructor returns a named Rule, so they can be passed to BatchResult::validate_rules or
+//! combined as Validators. The rule's name doubles as an error code, so every failure reads as
+//! "code: message", such as "length: 'ab' is shorter than 3 characters".
+//!
+//! Example:
+//! ```rust
+//! use allwhat::{prelude::*, rules};
+//!
+//! let checks = vec![rules::not_empty(), rules::length(3, 32), rules::email()];
+//! let batch = BatchResult::validate_rules("ab", &checks);
+//! assert_eq!(batch.failed_rules().collect::<Vec<_>>(), vec!["length", "email"]);
+//! ```
+
+use crate::local::*;
+use regex::Regex;
+
+const EMAIL: &str = r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]+@[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?(?:\.[A-Za-z0-9](?:[A-Za-z0-9-]{0,61}[A-Za-z0-9])?)+$";
+const URL: &str = r"^[A-Za-z][A-Za-z0-9+.-]*://[^\s/?#@]+(?:[/?#][^\s]*)?$";
+const UUID: &str = r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$";
+const ISO_8601: &str =
+  r"^(\d{4})-(\d{2})-(\d{2})(?:T(\d{2}):(\d{2}):(\d{2})(?:\.\d+)?(?:Z|[+-]\d{2}:\d{2})?)?$";
+
+/// The value must be between min and max, inclusive
+pub fn range<N>(min: N, max: N) -> Rule<N>
+where
+  N: PartialOrd + Display + Send + Sync + 'static,
+{
+  Rule::new("range", move |x: &N| match *x >= min && *x <= max {
+    true => Ok(()),
+    false => Err(format!("{} is not between {} and {}", x, min, max)),
+  })
+}
+
+/// The number of characters must be between min and max, inclusive
+pub fn length<S: AsRef<str>>(min: usize, max: usize) -> Rule<S> {
+  Rule::new("length", move |x: &S| {
+    let value = x.as_ref();
+    match value.chars().count() {
+      len if len < min => Err(format!("'{}' is shorter than {} characters", value, min)),
+      len if len > max => Err(format!("'{}' is longer than {} characters", value, max)),
+      _ => Ok(()),
+    }
+  })
+}
+
+/// The string must contain something other than whitespace
+pub fn not_empty<S: AsRef<str>>() -> Rule<S> {
+  Rule::new("not_empty", |x: &S| match x.as_ref().trim().is_empty() {
+    true => Err("the value is empty"),
+    false => Ok(()),
+  })
+}
+
+/// The string must match the regular expression
+///
+/// The pattern is compiled once, when the rule is created.
+pub fn pattern<S: AsRef<str>>(pattern: &str) -> Result<Rule<S>, regex::Error> {
+  let regex = Regex::new(pattern)?;
+  Ok(Rule::new("pattern", move |x: &S| {
+    match regex.is_match(x.as_ref()) {
+      true => Ok(()),
+      false => Err(format!("'{}' does not match {}", x.as_ref(), regex)),
+    }
+  }))
+}
+
+/// The string must look like an email address
+pub fn email<S: AsRef<str>>() -> Rule<S> {
+  matching("email", EMAIL, "is not a valid email address")
+}
+
+/// The string must be an absolute URL with a scheme and host
+pub fn url<S: AsRef<str>>() -> Rule<S> {
+  matching("url", URL, "is not a valid URL")
+}
+
+/// The string must be a UUID in its hyphenated form
+pub fn uuid<S: AsRef<str>>() -> Rule<S> {
+  matching("uuid", UUID, "is not a valid UUID")
+}
+
+/// The string must be an ISO-8601 date, optionally followed by a time
+///
+/// The date has to exist, so "2021-02-29" fails even though it has the right shape.
+pub fn iso8601<S: AsRef<str>>() -> Rule<S> {
+  let regex = Regex::new(ISO_8601).expect("The ISO-8601 pattern is valid");
+  Rule::new("iso8601", move |x: &S| {
+    let value = x.as_ref();
+    let parts: Option<Vec<u32>> = regex.captures(value).map(|caps| {
+      caps
+        .iter()
+        .skip(1)
+        .map(|part| part.map_or(0, |part| part.as_str().parse().unwrap_or(u32::MAX)))
+        .collect()
+    });
+    match parts {
+      Some(parts) if is_valid_date(parts[0], parts[1], parts[2]) && is_valid_time(&parts[3..]) => {
+        Ok(())
+      }
+      _ => Err(format!("'{}' is not a valid ISO-8601 date", value)),
+    }
+  })
+}
+
+/// The value must be one of the given options
+pub fn one_of<T, O>(options: impl IntoIterator<Item = O>) -> Rule<T>
+where
+  T: PartialEq<O> + Display,
+  O: Display + Send + Sync + 'static,
+{
+  let options: Vec<O> = options.into_iter().collect();
+  Rule::new("one_of", move |x: &T| {
+    match options.iter().any(|option| x == option) {
+      true => Ok(()),
+      false => {
+        let allowed: Vec<String> = options.iter().map(|option| option.to_string()).collect();
+        Err(format!("'{}' is not one of [{}]", x, allowed.join(", ")))
+      }
+    }
+  })
+}
+
+/// A rule for one of the built in patterns
+fn matching<S: AsRef<str>>(code: &str, pattern: &str, problem: &'static str) -> Rule<S> {
+  let regex = Regex::new(pattern).expect("The built in patterns are valid");
+  Rule::new(code, move |x: &S| match regex.is_match(x.as_ref()) {
+    true => Ok(()),
+    false => Err(format!("'{}' {}", x.as_ref(), problem)),
+  })
+}
+
+fn is_valid_date(year: u32, month: u32, day: u32) -> bool {
+  let leap = match (year % 4, year % 100, year % 400) {
+    (_, _, 0) => true,
+    (_, 0, _) => false,
+    (0, _, _) => true,
+    _ => false,
+  };
+  let days = match month {
+    1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+    4 | 6 | 9 | 11 => 30,
+    2 if leap => 29,
+    2 => 28,
+    _ => return false,
+  };
+  day >= 1 && day <= days
+}
+
+/// Missing times are parsed as midnight
+fn is_valid_time(time: &[u32]) -> bool {
+  time[0] < 24 && time[1] < 60 && time[2] < 60
+}
@@ -0,0 +1,96 @@
+//! Lazily splitting an iterator of results without collecting it
+//!
+//! SplitResult::map and ErrorGroup::unwrap_all both buffer the whole input, which doesn't work
+//! for streams too large to hold in memory. The adapter here yields the successful values one at
+//! a time and hands each error to a sink as it is found, so it can feed straight into the rest of
+//! an iterator chain.
+
+use crate::local::*;
+
+/// Somewhere to put the errors pulled out of a stream of results
+pub trait ErrorSink<E> {
+  /// Take the error from the input element at index
+  fn record(&mut self, index: usize, error: E);
+}
+
+impl<E: Debug> ErrorSink<E> for ErrorGroup {
+  fn record(&mut self, _index: usize, error: E) {
+    self.append(format!("{:#?}", error));
+  }
+}
+
+/// Keep the errors as they are
+impl<E> ErrorSink<E> for Vec<E> {
+  fn record(&mut self, _index: usize, error: E) {
+    self.push(error);
+  }
+}
+
+/// Keep the errors as they are, along with the input index of each one
+impl<E> ErrorSink<E> for Vec<(usize, E)> {
+  fn record(&mut self, index: usize, error: E) {
+    self.push((index, error));
+  }
+}
+
+/// An iterator over the successful values of another, sending the errors to a sink
+///
+/// Created by [`SplitErrors::split_errors`].
+#[derive(Debug)]
+pub struct SplitIter<'a, I, S> {
+  inner: std::iter::Enumerate<I>,
+  sink: &'a mut S,
+}
+
+impl<'a, I, S, T, E> Iterator for SplitIter<'a, I, S>
+where
+  I: Iterator<Item = Result<T, E>>,
+  S: ErrorSink<E>,
+{
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    for (index, item) in &mut self.inner {
+      match item {
+        Ok(value) => return Some(value),
+        Err(err) => self.sink.record(index, err),
+      }
+    }
+    None
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (0, self.inner.size_hint().1)
+  }
+}
+
+/// Adds split_errors to any iterator of results
+pub trait SplitErrors<T, E>: Iterator<Item = Result<T, E>> + Sized {
+  /// Yield only the successful values, pushing each error into the sink as it is reached
+  ///
+  /// Nothing is buffered, so the sink only holds the errors for the part of the input that has
+  /// been consumed so far.
+  ///
+  /// Example:
+  /// ```rust
+  /// use allwhat::prelude::*;
+  ///
+  /// let mut errors = ErrorGroup::new(Some("Bad Rows".to_string()));
+  /// let total: i32 = ["1", "x", "3", "y"]
+  ///   .iter()
+  ///   .map(|x| x.parse::<i32>())
+  ///   .split_errors(&mut errors)
+  ///   .sum();
+  ///
+  /// assert_eq!(total, 4);
+  /// assert_eq!(errors.len(), 2);
+  /// ```
+  fn split_errors<S: ErrorSink<E>>(self, sink: &mut S) -> SplitIter<'_, Self, S> {
+    SplitIter {
+      inner: self.enumerate(),
+      sink,
+    }
+  }
+}
+
+impl<T, E, I: Iterator<Item = Result<T, E>>> SplitErrors<T, E> for I {}
@@ -0,0 +1,50 @@
+//! Saving an accumulator's state so a failed step can be undone
+//!
+//! Types that are cheap to copy can use the *_cloned versions of the rollback constructors, which
+//! save the whole value. Types that are expensive to copy, or that aren't Clone at all, can
+//! implement Snapshot to save only what a step might touch.
+
+/// A value that can save its state and later go back to it
+///
+/// Example:
+/// ```rust
+/// use allwhat::{prelude::*, Snapshot};
+///
+/// /// An append only log, where undoing means truncating back to the saved length
+/// struct Log {
+///   lines: Vec<String>,
+/// }
+///
+/// impl Snapshot for Log {
+///   type Saved = usize;
+///
+///   fn snapshot(&self) -> usize {
+///     self.lines.len()
+///   }
+///
+///   fn restore(&mut self, saved: usize) {
+///     self.lines.truncate(saved);
+///   }
+/// }
+///
+/// let log = Log { lines: vec![] };
+/// let batch = BatchResult::fold_or_rollback(log, ["a", "", "c"].iter(), |log, line| {
+///   log.lines.push(format!("start {}", line));
+///   if line.is_empty() {
+///     return Err("Empty line");
+///   }
+///   log.lines.push(format!("end {}", line));
+///   Ok(())
+/// });
+/// assert_eq!(batch.count_error(), 1);
+/// assert_eq!(batch.value().lines, vec!["start a", "end a", "start c", "end c"]);
+/// ```
+pub trait Snapshot {
+  /// Whatever is needed to put the value back the way it was
+  type Saved;
+
+  fn snapshot(&self) -> Self::Saved;
+
+  /// Return to the state when the snapshot was taken
+  fn restore(&mut self, saved: Self::Saved);
+}
@@ -1,8 +1,14 @@
 //! Iterator based functions that allow partial success
 //!
-//! TODO: Add the ? functionality for split results to throw errors
+//! Errors can be thrown with `split_result.into_result()?`, or with `split_result?` directly when
+//! the nightly "try_trait" feature is enabled.
 
-use super::group::{ErrorGroup, Grouper};
+use super::{
+  budget::{remaining, Aborted, ErrorBudget},
+  group::{ErrorGroup, Grouper},
+};
+
+use std::{collections::HashMap, hash::Hash};
 
 #[derive(Debug)]
 pub struct SplitResult<T>
@@ -11,6 +17,13 @@ pub struct SplitResult<T>
 {
   values: Vec<T>,
   errors: Option<ErrorGroup>,
+  /// The position in the input of the element behind each error, in the same order as the errors
+  failed: Vec<usize>,
+  /// The position in the input of the element behind each value, in the same order as the values
+  passed: Vec<usize>,
+  /// The number of input elements seen, including any that produced no values or errors
+  inputs: usize,
+  aborted: Option<Aborted>,
 }
 
 impl<T> SplitResult<T> {
@@ -22,38 +35,311 @@ impl<T> SplitResult<T> {
     F: Fn(U) -> Result<T, E>,
     E: std::fmt::Display + std::fmt::Debug + Sync + Send + 'static,
   {
-    let mut values = vec![];
-    let mut group = ErrorGroup::new(None);
-    for item in list {
-      match func(item) {
-        Ok(value) => values.push(value),
-        Err(err) => group.append(format!("{:#?}", err)),
+    let mut result = SplitResult::empty();
+    for (index, item) in list.enumerate() {
+      result.add(index, func(item));
+    }
+    result
+  }
+
+  /// Like map, but stops early once the error budget is spent
+  ///
+  /// The rest of the list is counted without being processed, so the result can report how much
+  /// work was skipped.
+  pub fn map_with_budget<U, E, F>(
+    list: impl Iterator<Item = U>,
+    budget: &ErrorBudget<E>,
+    func: F,
+  ) -> SplitResult<T>
+  where
+    F: Fn(U) -> Result<T, E>,
+    E: std::fmt::Display + std::fmt::Debug + Sync + Send + 'static,
+  {
+    let mut result = SplitResult::empty();
+    let mut spent = None;
+    let mut list = list.enumerate();
+    for (index, item) in &mut list {
+      let item_result = func(item);
+      let errors = result.failed.len() + item_result.is_err() as usize;
+      let reason = budget.check(index as u64 + 1, errors as u64, item_result.as_ref().err());
+      result.add(index, item_result);
+
+      if let Some(reason) = reason {
+        spent = Some((reason, index as u64 + 1));
+        break;
       }
     }
 
+    if let Some((reason, processed)) = spent {
+      result.aborted = Some(Aborted {
+        reason,
+        processed,
+        remaining: remaining(&list),
+      });
+    }
+    result
+  }
+
+  /// Like map, but also sorts the errors into buckets by a key taken from each one
+  ///
+  /// This is for deciding who handles which failures, such as missing fields versus duplicate
+  /// keys. Each bucket's group is labelled with its key, and the SplitResult still holds all the
+  /// errors with their indices.
+  pub fn map_classified<U, E, K, C, F>(
+    list: impl Iterator<Item = U>,
+    classify: C,
+    func: F,
+  ) -> (SplitResult<T>, HashMap<K, ErrorGroup>)
+  where
+    F: Fn(U) -> Result<T, E>,
+    C: Fn(&E) -> K,
+    K: Hash + Eq + std::fmt::Display,
+    E: std::fmt::Display + std::fmt::Debug + Sync + Send + 'static,
+  {
+    let mut result = SplitResult::empty();
+    let mut buckets = HashMap::new();
+    for (index, item) in list.enumerate() {
+      let item_result = func(item);
+      if let Err(err) = &item_result {
+        let key = classify(err);
+        let label = key.to_string();
+        buckets
+          .entry(key)
+          .or_insert_with(|| ErrorGroup::new(Some(label)))
+          .append(format!("{:#?}", err));
+      }
+      result.add(index, item_result);
+    }
+    (result, buckets)
+  }
+
+  /// Like map, but hands back each failed input paired with its error instead of dropping it
+  ///
+  /// The function borrows each item so the failures can be sent to a retry queue or fixed and run
+  /// again. The SplitResult still records the errors and their indices as usual.
+  pub fn map_dead_letter<U, E, F>(
+    list: impl Iterator<Item = U>,
+    func: F,
+  ) -> (SplitResult<T>, Vec<(U, E)>)
+  where
+    F: Fn(&U) -> Result<T, E>,
+    E: std::fmt::Display + std::fmt::Debug + Sync + Send + 'static,
+  {
+    let mut result = SplitResult::empty();
+    let mut dead_letters = vec![];
+    for (index, item) in list.enumerate() {
+      match func(&item) {
+        Ok(value) => result.add::<&E>(index, Ok(value)),
+        Err(err) => {
+          result.add::<&E>(index, Err(&err));
+          dead_letters.push((item, err));
+        }
+      }
+    }
+    (result, dead_letters)
+  }
+
+  /// A result with nothing in it yet
+  pub(crate) fn empty() -> SplitResult<T> {
     SplitResult {
-      values,
-      errors: match group.len() {
-        0 => None,
-        _ => Some(group),
-      },
+      values: vec![],
+      errors: None,
+      failed: vec![],
+      passed: vec![],
+      inputs: 0,
+      aborted: None,
+    }
+  }
+
+  /// Sort the result for the input element at index into the values or the errors
+  pub(crate) fn add<E: std::fmt::Debug>(&mut self, index: usize, result: Result<T, E>) {
+    self.inputs = self.inputs.max(index + 1);
+    match result {
+      Ok(value) => {
+        self.values.push(value);
+        self.passed.push(index);
+      }
+      Err(err) => {
+        self
+          .errors
+          .get_or_insert_with(|| ErrorGroup::new(None))
+          .append(format!("{:#?}", err));
+        self.failed.push(index);
+      }
+    }
+  }
+
+  /// Run another fallible stage over the successful values, keeping the earlier errors
+  ///
+  /// The new errors are nested under the root group in a sub-group labelled with the stage name,
+  /// so the display shows which stage each failure came from. Failed indices still point at the
+  /// original input.
+  pub fn and_then<U, E, F>(self, stage: &str, func: F) -> SplitResult<U>
+  where
+    F: Fn(T) -> Result<U, E>,
+    E: std::fmt::Display + std::fmt::Debug + Sync + Send + 'static,
+  {
+    self.flat_map(stage, |value| func(value).map(std::iter::once))
+  }
+
+  /// Like and_then, but each value can expand into any number of new values
+  ///
+  /// Every value produced keeps the input index of the value it came from.
+  pub fn flat_map<U, I, E, F>(self, stage: &str, func: F) -> SplitResult<U>
+  where
+    F: Fn(T) -> Result<I, E>,
+    I: IntoIterator<Item = U>,
+    E: std::fmt::Display + std::fmt::Debug + Sync + Send + 'static,
+  {
+    let mut result = SplitResult {
+      values: vec![],
+      errors: self.errors,
+      failed: self.failed,
+      passed: vec![],
+      inputs: self.inputs,
+      aborted: self.aborted,
+    };
+    let mut stage_errors = SplitResult::<U>::empty();
+    for (index, value) in self.passed.into_iter().zip(self.values) {
+      match func(value) {
+        Ok(values) => {
+          for value in values {
+            result.add::<E>(index, Ok(value));
+          }
+        }
+        Err(err) => stage_errors.add::<E>(index, Err(err)),
+      }
+    }
+    if let Some(group) = stage_errors.errors {
+      result
+        .errors
+        .get_or_insert_with(|| ErrorGroup::new(None))
+        .push_group(group.set_label(stage.to_string()));
+      result.failed.extend(stage_errors.failed);
+    }
+    result
+  }
+
+  /// Transform the successful values with a function that can't fail
+  pub fn map_values<U, F>(self, func: F) -> SplitResult<U>
+  where
+    F: Fn(T) -> U,
+  {
+    SplitResult {
+      values: self.values.into_iter().map(func).collect(),
+      errors: self.errors,
+      failed: self.failed,
+      passed: self.passed,
+      inputs: self.inputs,
+      aborted: self.aborted,
+    }
+  }
+
+  /// Append the values and errors of another result, as though both inputs were one list
+  ///
+  /// The other result's indices are shifted past the end of this one's input, and its errors are
+  /// nested as a sub-group so their labels are kept.
+  pub fn merge(mut self, other: SplitResult<T>) -> SplitResult<T> {
+    let offset = self.inputs;
+    self.inputs += other.inputs;
+    self.values.extend(other.values);
+    self
+      .passed
+      .extend(other.passed.iter().map(|index| index + offset));
+    if let Some(group) = other.errors {
+      self
+        .errors
+        .get_or_insert_with(|| ErrorGroup::new(None))
+        .push_group(group);
+      self
+        .failed
+        .extend(other.failed.iter().map(|index| index + offset));
     }
+    self.aborted = self.aborted.or(other.aborted);
+    self
+  }
+
+  /// The successful values, in the order of their inputs
+  pub fn values(&self) -> &[T] {
+    &self.values
+  }
+
+  /// The errors found, if there were any
+  pub fn errors(&self) -> Option<&ErrorGroup> {
+    self.errors.as_ref()
+  }
+
+  /// The input index of each element that failed, matching the order of the errors
+  ///
+  /// Every error has an index, so the two always line up. Errors thrown into a SplitResult with ?
+  /// didn't come from an input element, so each is counted as an input of its own.
+  pub fn failed_indices(&self) -> &[usize] {
+    &self.failed
+  }
+
+  /// The input index of each element that succeeded, matching the order of the values
+  pub fn passed_indices(&self) -> &[usize] {
+    &self.passed
+  }
+
+  /// Details of why processing stopped early, if an error budget was broken
+  pub fn aborted(&self) -> Option<&Aborted> {
+    self.aborted.as_ref()
+  }
+
+  /// Break the result into its values and errors, keeping the values even if there are errors
+  pub fn into_parts(self) -> (Vec<T>, Option<ErrorGroup>) {
+    (self.values, self.errors)
+  }
+
+  /// Whether every element succeeded
+  pub fn is_ok(&self) -> bool {
+    self.errors.is_none()
+  }
+
+  /// The number of values produced
+  ///
+  /// This matches the number of inputs that succeeded until a flat_map expands some of them into
+  /// several values, or none.
+  pub fn count_valid(&self) -> usize {
+    self.values.len()
+  }
+
+  /// The number of errors found
+  pub fn count_error(&self) -> usize {
+    self.errors.as_ref().map_or(0, ErrorGroup::len)
+  }
+
+  /// The total number of input elements processed
+  ///
+  /// This is the count that merge offsets the indices by, so it doesn't change when a flat_map
+  /// turns one input into a different number of values.
+  pub fn count(&self) -> usize {
+    self.inputs
   }
 }
 
 impl<T> Grouper for SplitResult<T> {
   type Result = Vec<T>;
 
+  /// Only the errors carry context, so a result with no errors is left unchanged
   fn context(self, ctx: String) -> SplitResult<T> {
     SplitResult {
-      errors: Some(match self.errors {
-        Some(group) => group.set_label(ctx),
-        None => ErrorGroup::new(Some(ctx)),
-      }),
+      errors: self.errors.map(|group| group.context(ctx)),
       ..self
     }
   }
 
+  fn with_context<F>(self, func: F) -> SplitResult<T>
+  where
+    F: FnOnce() -> String,
+  {
+    match self.errors {
+      Some(_) => self.context(func()),
+      None => self,
+    }
+  }
+
   /// Convert this to a result, Ok(values) if errors is None and Err(errors) if not
   fn as_result<E: From<ErrorGroup>>(self) -> Result<Self::Result, E> {
     match self.errors {
@@ -62,3 +348,39 @@ impl<T> Grouper for SplitResult<T> {
     }
   }
 }
+
+#[cfg(feature = "try_trait")]
+impl<T> std::ops::Try for SplitResult<T> {
+  type Output = Vec<T>;
+  type Residual = Result<std::convert::Infallible, ErrorGroup>;
+
+  fn from_output(values: Self::Output) -> Self {
+    SplitResult {
+      passed: (0..values.len()).collect(),
+      inputs: values.len(),
+      values,
+      ..SplitResult::empty()
+    }
+  }
+
+  fn branch(self) -> std::ops::ControlFlow<Self::Residual, Self::Output> {
+    match self.into_result() {
+      Ok(values) => std::ops::ControlFlow::Continue(values),
+      Err(group) => std::ops::ControlFlow::Break(Err(group)),
+    }
+  }
+}
+
+#[cfg(feature = "try_trait")]
+impl<T> std::ops::FromResidual<Result<std::convert::Infallible, ErrorGroup>> for SplitResult<T> {
+  fn from_residual(residual: Result<std::convert::Infallible, ErrorGroup>) -> Self {
+    match residual {
+      Err(group) => SplitResult {
+        failed: (0..group.len()).collect(),
+        inputs: group.len(),
+        errors: Some(group),
+        ..SplitResult::empty()
+      },
+    }
+  }
+}
@@ -8,10 +8,12 @@
 //! left to a derive
 
 mod traits;
-// pub use traits::*;
+pub use traits::*;
 
 pub mod impls;
 // pub use impls::*;
 
+// The Patchwork based version is still a sketch, so it is only built on request and isn't exported
+#[cfg(feature = "transaction")]
+#[allow(dead_code)]
 mod transaction;
-pub use transaction::*;
@@ -1,6 +1,7 @@
 //! The tools needed to implement a generic try with rollback
 
 use crate::local::*;
+use anyhow::Error as AnyhowError;
 
 /// An object that sets
 pub trait TryMutPatch {}
@@ -13,9 +14,8 @@ pub trait TryMutAction {
   /// This is the stored information needed for
   type Patch: TryMutPatch;
 
-  type Error: Into<AnyhowError> + Display + Debug + Send + Sync + 'static;
-
   /// The errors that can be returned
+  type Error: Into<AnyhowError> + Display + Debug + Send + Sync + 'static;
 
   /// Patch items needed to restore upon failure
   fn patch(&self, item: &Self::Item) -> Self::Patch;
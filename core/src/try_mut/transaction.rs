@@ -39,9 +39,11 @@ where
 {
   type Error: Debug + Display + Send + Sync + 'static;
 
-  fn apply(&self, item: impl Revertable) -> PatchResult<Self::Error, Self>;
-  fn undo(item: impl Revertable, patch: Patch<Self::Error, Self>)
-    -> PatchResult<Self::Error, Self>;
+  fn apply(&self, item: impl Revertable) -> PatchResult<(), Self::Error, Self>;
+  fn undo(
+    item: impl Revertable,
+    patch: Patch<Self::Error, Self>,
+  ) -> PatchResult<(), Self::Error, Self>;
 }
 
 /// A set of closures needed to try an action
@@ -53,7 +55,7 @@ pub trait Revertable {
   type Action: Invertable<Error = Self::Error>;
 
   /// The actual function to run
-  fn run(&mut self, action: Self::Action) -> PatchResult<Self::Error, Self::Action>;
+  fn run(&mut self, action: Self::Action) -> PatchResult<(), Self::Error, Self::Action>;
 
   /// How to handle errors. Default is to just restore, but the response can be based on error type
   ///
@@ -99,5 +101,6 @@ impl<R, E, A> std::error::Error for PatchResult<R, E, A>
 where
   A: Invertable<Error = E> + Debug,
   E: Debug + Display + Send + Sync + 'static,
+  R: Debug,
 {
 }
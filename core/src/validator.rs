@@ -0,0 +1,270 @@
+//! Reusable validators that can be combined into larger rule sets
+//!
+//! BatchResult::validate takes a one-off list of closures. A Validator is a value of its own, so
+//! the same checks can be shared, combined with and/or/not, made conditional, and pointed at the
+//! fields or elements of a bigger type. Named rules are the leaves.
+
+use crate::local::*;
+use std::marker::PhantomData;
+
+/// A check, or a combination of checks, against a value of type T
+///
+/// Example:
+/// ```rust
+/// use allwhat::{field, prelude::*};
+///
+/// struct Order {
+///   quantity: i32,
+///   express: bool,
+///   address: String,
+/// }
+///
+/// let positive = Rule::new("positive", |x: &i32| match *x > 0 {
+///   true => Ok(()),
+///   false => Err(format!("{} is not positive", x)),
+/// });
+/// let has_address = Rule::new("has_address", |x: &String| match x.is_empty() {
+///   true => Err("no address"),
+///   false => Ok(()),
+/// });
+///
+/// let order_rules = field(|o: &Order| &o.quantity, positive)
+///   .and(field(|o: &Order| &o.address, has_address).when(|o: &Order| o.express));
+///
+/// let order = Order {
+///   quantity: 0,
+///   express: true,
+///   address: String::new(),
+/// };
+/// let batch = BatchResult::validate_with(order, &order_rules);
+/// assert_eq!(batch.count(), 2);
+/// assert_eq!(batch.count_error(), 2);
+/// ```
+pub trait Validator<T: ?Sized> {
+  /// Run the checks, adding any failures to the group and returning how many checks were run
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64;
+
+  /// Run the checks, collecting every failure
+  fn validate(&self, value: &T) -> Result<(), ErrorGroup> {
+    let mut errors = ErrorGroup::new(None);
+    self.validate_into(value, &mut errors);
+    match errors.is_empty() {
+      true => Ok(()),
+      false => Err(errors),
+    }
+  }
+
+  /// Require both validators to pass, reporting the failures of each
+  fn and<V: Validator<T>>(self, other: V) -> And<Self, V>
+  where
+    Self: Sized,
+  {
+    And(self, other)
+  }
+
+  /// Require at least one of the validators to pass
+  fn or<V: Validator<T>>(self, other: V) -> Or<Self, V>
+  where
+    Self: Sized,
+  {
+    Or(self, other)
+  }
+
+  /// Fail with the message when this validator passes, and pass when it fails
+  fn not(self, msg: &str) -> Not<Self>
+  where
+    Self: Sized,
+  {
+    Not {
+      inner: self,
+      msg: msg.to_string(),
+    }
+  }
+
+  /// Only run the checks when the condition holds for the value
+  fn when<C: Fn(&T) -> bool>(self, cond: C) -> When<Self, C>
+  where
+    Self: Sized,
+  {
+    When { inner: self, cond }
+  }
+}
+
+/// Validators can be shared by reference between several combinations
+impl<T: ?Sized, V: Validator<T>> Validator<T> for &V {
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64 {
+    (*self).validate_into(value, errors)
+  }
+}
+
+/// A rule is a single check, reported with its name the same way validate_rules does
+///
+/// Prerequisites added with requires are ignored here, because a validator only sees one rule at
+/// a time. Use BatchResult::validate_rules for rules that depend on each other.
+impl<T> Validator<T> for Rule<T> {
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64 {
+    if let RuleOutcome::Failed(msg) = self.check(value) {
+      errors.push(format!("{}: {}", self.name(), msg));
+    }
+    1
+  }
+}
+
+/// Created by [`Validator::and`]
+#[derive(Debug)]
+pub struct And<A, B>(A, B);
+
+impl<T: ?Sized, A: Validator<T>, B: Validator<T>> Validator<T> for And<A, B> {
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64 {
+    self.0.validate_into(value, errors) + self.1.validate_into(value, errors)
+  }
+}
+
+/// Created by [`Validator::or`]
+#[derive(Debug)]
+pub struct Or<A, B>(A, B);
+
+impl<T: ?Sized, A: Validator<T>, B: Validator<T>> Validator<T> for Or<A, B> {
+  /// Both sides are always run, but their failures only count when neither passes
+  ///
+  /// A side that runs no checks, such as each over an empty collection, has nothing failing, so
+  /// the Or passes without a check being counted.
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64 {
+    let mut alternatives = ErrorGroup::new(Some("None of the alternatives passed".to_string()));
+    let mut checks = self.0.validate_into(value, &mut alternatives);
+    let failed = alternatives.len();
+    checks += self.1.validate_into(value, &mut alternatives);
+    if failed > 0 && alternatives.len() > failed {
+      errors.push_group(alternatives);
+    }
+    checks
+  }
+}
+
+/// Created by [`Validator::not`]
+#[derive(Debug)]
+pub struct Not<V> {
+  inner: V,
+  msg: String,
+}
+
+impl<T: ?Sized, V: Validator<T>> Validator<T> for Not<V> {
+  /// The inner checks count as a single one, since only their combined result matters
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64 {
+    if self.inner.validate(value).is_ok() {
+      errors.push(&self.msg);
+    }
+    1
+  }
+}
+
+/// Created by [`Validator::when`]
+pub struct When<V, C> {
+  inner: V,
+  cond: C,
+}
+
+impl<V: Debug, C> Debug for When<V, C> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.debug_struct("When").field("inner", &self.inner).finish()
+  }
+}
+
+impl<T: ?Sized, V: Validator<T>, C: Fn(&T) -> bool> Validator<T> for When<V, C> {
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64 {
+    match (self.cond)(value) {
+      true => self.inner.validate_into(value, errors),
+      false => 0,
+    }
+  }
+}
+
+/// Run a validator against every element of a collection
+///
+/// The failures for each element are kept in their own group, labelled with its position.
+pub fn each<T, V: Validator<T>>(validator: V) -> Each<V, T> {
+  Each {
+    inner: validator,
+    _item: PhantomData,
+  }
+}
+
+/// Created by [`each`]
+pub struct Each<V, T> {
+  inner: V,
+  _item: PhantomData<fn(&T)>,
+}
+
+impl<V: Debug, T> Debug for Each<V, T> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.debug_struct("Each").field("inner", &self.inner).finish()
+  }
+}
+
+impl<C, T, V> Validator<C> for Each<V, T>
+where
+  C: ?Sized,
+  for<'a> &'a C: IntoIterator<Item = &'a T>,
+  V: Validator<T>,
+{
+  fn validate_into(&self, value: &C, errors: &mut ErrorGroup) -> u64 {
+    let mut checks = 0;
+    for (index, item) in value.into_iter().enumerate() {
+      let mut item_errors = ErrorGroup::new(Some(format!("Item {}", index)));
+      checks += self.inner.validate_into(item, &mut item_errors);
+      if !item_errors.is_empty() {
+        errors.push_group(item_errors);
+      }
+    }
+    checks
+  }
+}
+
+/// Run a validator against a part of the value, picked out by the getter
+pub fn field<T, U, G, V>(getter: G, validator: V) -> Field<G, V, U>
+where
+  T: ?Sized,
+  U: ?Sized,
+  G: Fn(&T) -> &U,
+  V: Validator<U>,
+{
+  Field {
+    getter,
+    inner: validator,
+    _field: PhantomData,
+  }
+}
+
+/// Created by [`field`]
+pub struct Field<G, V, U: ?Sized> {
+  getter: G,
+  inner: V,
+  _field: PhantomData<fn(&U)>,
+}
+
+impl<G, V: Debug, U: ?Sized> Debug for Field<G, V, U> {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.debug_struct("Field").field("inner", &self.inner).finish()
+  }
+}
+
+impl<T, U, G, V> Validator<T> for Field<G, V, U>
+where
+  T: ?Sized,
+  U: ?Sized,
+  G: Fn(&T) -> &U,
+  V: Validator<U>,
+{
+  fn validate_into(&self, value: &T, errors: &mut ErrorGroup) -> u64 {
+    self.inner.validate_into((self.getter)(value), errors)
+  }
+}
+
+impl<T> BatchResult<T> {
+  /// Run the value through a composed validator, counting every check that was run
+  pub fn validate_with<V: Validator<T>>(value: T, validator: &V) -> BatchResult<T> {
+    let mut errors = ErrorGroup::new(None);
+    let count = validator.validate_into(&value, &mut errors);
+    BatchResult::from_checks(value, count, errors)
+  }
+}
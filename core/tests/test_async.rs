@@ -0,0 +1,150 @@
+//! Run the async tools on a local runtime against a mock service
+#![cfg(feature = "async")]
+
+use allwhat::{prelude::*, AsyncRule};
+use futures::{FutureExt, StreamExt};
+use std::sync::{
+  atomic::{AtomicUsize, Ordering},
+  Arc,
+};
+use std::time::Duration;
+
+/// A stand in for a remote service, tracking how many calls are in flight at once
+#[derive(Default)]
+struct MockService {
+  in_flight: AtomicUsize,
+  max_in_flight: AtomicUsize,
+}
+
+impl MockService {
+  /// Later ids respond faster, so the calls finish in the reverse order they were made
+  async fn fetch(&self, id: u64) -> Result<String, String> {
+    let current = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+    self.max_in_flight.fetch_max(current, Ordering::SeqCst);
+
+    tokio::time::sleep(Duration::from_millis(20 - id)).await;
+
+    self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    match id % 4 {
+      0 => Err(format!("Record {} not found", id)),
+      _ => Ok(format!("Record {}", id)),
+    }
+  }
+}
+
+#[tokio::test]
+async fn test_from_futures() {
+  let service = MockService::default();
+  let split = SplitResult::from_futures((1..=10).map(|id| service.fetch(id)), 3).await;
+
+  assert_eq!(service.max_in_flight.load(Ordering::SeqCst), 3);
+  assert_eq!(split.count(), 10);
+  assert_eq!(split.failed_indices(), &[3, 7]);
+  assert_eq!(split.values()[0], "Record 1");
+  assert_eq!(split.values()[7], "Record 10");
+
+  let errors: Vec<&String> = split.errors().unwrap().iter().collect();
+  assert!(errors[0].contains("Record 4 not found"));
+  assert!(errors[1].contains("Record 8 not found"));
+}
+
+#[tokio::test]
+async fn test_from_stream() {
+  let service = MockService::default();
+  let requests = futures::stream::iter(1..=8).map(|id| service.fetch(id));
+  let split = SplitResult::from_stream(requests, 8).await;
+
+  assert_eq!(service.max_in_flight.load(Ordering::SeqCst), 8);
+  assert_eq!(split.count_valid(), 6);
+  assert_eq!(split.failed_indices(), &[3, 7]);
+}
+
+#[tokio::test]
+async fn test_validate_async() {
+  let service = Arc::new(MockService::default());
+  // Each rule looks up a different record for the value, so ids 4 and 8 fail
+  let mut rules: Vec<AsyncRule<u64>> = (1..=8)
+    .map(|offset| {
+      let service = service.clone();
+      AsyncRule::new(&format!("lookup {}", offset), move |base: &u64| {
+        let service = service.clone();
+        let id = base + offset;
+        async move { service.fetch(id).await.map(|_| ()) }.boxed()
+      })
+    })
+    .collect();
+  rules.push(
+    AsyncRule::new("slow", |_: &u64| {
+      async {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        Ok::<(), String>(())
+      }
+      .boxed()
+    })
+    .timeout(Duration::from_millis(30)),
+  );
+  rules.push(
+    AsyncRule::new("after slow", |_: &u64| {
+      async { Ok::<(), String>(()) }.boxed()
+    })
+    .requires("slow"),
+  );
+
+  let batch = BatchResult::validate_async(0, &rules, 2).await;
+  assert_eq!(service.max_in_flight.load(Ordering::SeqCst), 2);
+  assert_eq!(batch.count(), 9);
+  assert_eq!(
+    batch.skipped_rules().collect::<Vec<_>>(),
+    vec!["after slow"]
+  );
+  assert_eq!(
+    batch.failed_rules().collect::<Vec<_>>(),
+    vec!["lookup 4", "lookup 8", "slow"]
+  );
+  assert_eq!(
+    batch.rule_outcome("slow"),
+    Some(&RuleOutcome::Failed("timed out after 30ms".to_string()))
+  );
+
+  let errors: Vec<_> = batch.into_result().unwrap_err().into_iter().collect();
+  assert_eq!(errors[0], "lookup 4: Record 4 not found");
+}
+
+#[tokio::test]
+async fn test_validate_async_bad_prerequisites() {
+  fn pass(_: &u32) -> futures::future::BoxFuture<'_, Result<(), String>> {
+    async { Ok(()) }.boxed()
+  }
+  let rules = vec![
+    AsyncRule::new("is_number", pass).describe("Parses as a number"),
+    AsyncRule::new("typo", pass).requires("is_numbr"),
+    AsyncRule::new("loop_a", pass).requires("loop_b"),
+    AsyncRule::new("loop_b", pass).requires("loop_a"),
+    AsyncRule::new("after_loop", pass).requires("loop_a"),
+  ];
+  assert_eq!(rules[0].description(), Some("Parses as a number"));
+
+  // Bad prerequisites are handled the same way as for the sync rules
+  let batch = BatchResult::validate_async(5, &rules, 2).await;
+  assert!(!batch.is_ok());
+  assert_eq!(
+    batch.failed_rules().collect::<Vec<_>>(),
+    vec!["typo", "loop_a", "loop_b"]
+  );
+  assert_eq!(
+    batch.rule_outcome("typo"),
+    Some(&RuleOutcome::Failed(
+      "requires unknown rules is_numbr".to_string()
+    ))
+  );
+  assert_eq!(
+    batch.rule_outcome("loop_b"),
+    Some(&RuleOutcome::Failed(
+      "requires rules that form a cycle: loop_a".to_string()
+    ))
+  );
+  assert_eq!(
+    batch.skipped_rules().collect::<Vec<_>>(),
+    vec!["after_loop"]
+  );
+}
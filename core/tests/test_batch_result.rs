@@ -127,3 +127,498 @@ let adder : Box<dyn Fn(&mut TestAcc, i32, i32) -> PoisonErr<AnyhowError>> =
 }
 
 */
+
+#[test]
+fn test_into_result() {
+  use allwhat::prelude::*;
+
+  fn total(list: Vec<i32>) -> Result<i32, ErrorGroup> {
+    let batch = BatchResult::fold(0, list.into_iter(), |acc, x| match x < 0 {
+      true => Err(format!("Negative value {}", x)),
+      false => {
+        *acc += x;
+        Ok(())
+      }
+    });
+    batch.into_result()
+  }
+
+  assert_eq!(total(vec![1, 2, 3]).unwrap(), 6);
+  assert_eq!(total(vec![1, -2, -3]).unwrap_err().len(), 2);
+
+  // Appended errors aren't functions run, so there can be more errors than the count
+  let mut batch = BatchResult::new(0);
+  batch.append("Missing header");
+  assert_eq!(batch.count(), 0);
+  assert_eq!(batch.count_error(), 1);
+  assert_eq!(batch.count_valid(), 0);
+}
+
+#[test]
+fn test_error_budget() {
+  use allwhat::prelude::*;
+
+  // The ratio only kicks in after the warm up, even though the first item fails
+  let budget = ErrorBudget::new().max_ratio(0.5, 10);
+  let batch =
+    BatchResult::fold_with_budget(0, 0..100, &budget, |acc, x| match x < 20 && x % 3 != 1 {
+      true => {
+        *acc += x;
+        Ok(())
+      }
+      false => Err(format!("Bad value {}", x)),
+    });
+  let aborted = batch.aborted().unwrap();
+  assert_eq!(aborted.processed, 27);
+  assert_eq!(aborted.remaining, Some(73));
+  assert_eq!(batch.count(), 27);
+  assert_eq!(batch.count_error(), 14);
+
+  let mut seen = 0;
+  let budget = ErrorBudget::new().max_errors(2);
+  let batch = BatchResult::<()>::foreach_with_budget(0..10, &budget, &mut |x| {
+    seen += 1;
+    match x % 2 {
+      0 => Ok(()),
+      _ => Err("Odd"),
+    }
+  });
+  assert_eq!(seen, 4);
+  assert_eq!(batch.count_error(), 2);
+  assert_eq!(batch.aborted().unwrap().remaining, Some(6));
+
+  // A lazy input that can't tell its length isn't run just to count what was left
+  let mut seen = 0;
+  let lazy = (0..10).filter(|x| x % 3 != 0).inspect(|_| seen += 1);
+  let batch = BatchResult::<()>::foreach_with_budget(lazy, &budget, &mut |_| Err("Bad"));
+  assert_eq!(seen, 2);
+  assert_eq!(batch.aborted().unwrap().remaining, None);
+  assert!(batch
+    .aborted()
+    .unwrap()
+    .to_string()
+    .starts_with("Aborted after 2 items with the rest left unprocessed"));
+}
+
+#[test]
+fn test_retry() {
+  use allwhat::prelude::*;
+
+  let mut calls = 0;
+  let policy = RetryPolicy::new(3);
+  let batch = BatchResult::foreach_with_retry(1..=3, &policy, &mut |x: &i32| {
+    calls += 1;
+    match *x {
+      2 => Err(format!("Flaky {}", x)),
+      _ => Ok(()),
+    }
+  });
+
+  assert_eq!(calls, 5);
+  assert_eq!(batch.count(), 3);
+  assert_eq!(batch.count_error(), 1);
+  let errors = batch.as_result::<ErrorGroup>().unwrap_err();
+  assert!(errors.to_string().contains("Failed after 3 attempts"));
+}
+
+#[test]
+fn test_named_rules() {
+  use allwhat::prelude::*;
+
+  #[derive(Debug)]
+  struct User {
+    name: String,
+    age: u32,
+  }
+
+  let rules = vec![
+    Rule::new("name_present", |user: &User| match user.name.is_empty() {
+      true => Err("the name is empty"),
+      false => Ok(()),
+    }),
+    Rule::new("adult", |user: &User| match user.age >= 18 {
+      true => Ok(()),
+      false => Err(format!("{} is under 18", user.age)),
+    })
+    .describe("Accounts can only be opened by adults"),
+  ];
+
+  let batch = BatchResult::validate_rules(
+    User {
+      name: "Sam".to_string(),
+      age: 12,
+    },
+    &rules,
+  );
+  assert_eq!(batch.count(), 2);
+  assert_eq!(batch.count_error(), 1);
+  assert_eq!(
+    batch.rule_outcome("name_present"),
+    Some(&RuleOutcome::Passed)
+  );
+  assert_eq!(
+    batch.rule_outcome("adult"),
+    Some(&RuleOutcome::Failed("12 is under 18".to_string()))
+  );
+  assert_eq!(batch.rule_outcome("missing"), None);
+  assert_eq!(batch.failed_rules().collect::<Vec<_>>(), vec!["adult"]);
+
+  let report = &batch.rule_reports()[1];
+  assert_eq!(
+    report.description.as_deref(),
+    Some("Accounts can only be opened by adults")
+  );
+  assert_eq!(report.to_string(), "adult: failed - 12 is under 18");
+
+  let errors = batch.into_result().unwrap_err();
+  assert_eq!(errors.iter().next().unwrap(), "adult: 12 is under 18");
+}
+
+#[test]
+fn test_rule_dependencies() {
+  use allwhat::prelude::*;
+
+  let is_json = |text: &String| match text.trim_start().starts_with('{') {
+    true => Ok(()),
+    false => Err("not a JSON object"),
+  };
+  let has_field = |field: &'static str| {
+    move |text: &String| match text.contains(&format!("\"{}\"", field)) {
+      true => Ok(()),
+      false => Err(format!("no {} field", field)),
+    }
+  };
+  // Registered out of order, so the prerequisites have to be run first
+  let rules = vec![
+    Rule::new("has_version", has_field("version")).requires("has_name"),
+    Rule::new("has_name", has_field("name")).requires("is_json"),
+    Rule::new("is_json", is_json),
+    Rule::new("not_empty", |text: &String| match text.is_empty() {
+      true => Err("empty"),
+      false => Ok(()),
+    }),
+  ];
+
+  let batch = BatchResult::validate_rules("name: x".to_string(), &rules);
+  assert_eq!(batch.count(), 2);
+  assert_eq!(batch.count_error(), 1);
+  assert_eq!(batch.count_skipped(), 2);
+  assert_eq!(batch.failed_rules().collect::<Vec<_>>(), vec!["is_json"]);
+  assert_eq!(
+    batch.skipped_rules().collect::<Vec<_>>(),
+    vec!["has_version", "has_name"]
+  );
+  assert_eq!(
+    batch.rule_outcome("has_name"),
+    Some(&RuleOutcome::Skipped(vec!["is_json".to_string()]))
+  );
+  assert_eq!(
+    batch.rule_reports()[0].to_string(),
+    "has_version: skipped - requires has_name"
+  );
+  assert!(batch
+    .to_string()
+    .starts_with("Batch Errors: 1 of 2 passed, 2 skipped, 1 error"));
+
+  let batch = BatchResult::validate_rules(r#"{"name": "x"}"#.to_string(), &rules);
+  assert_eq!(batch.count(), 4);
+  assert_eq!(
+    batch.failed_rules().collect::<Vec<_>>(),
+    vec!["has_version"]
+  );
+  assert_eq!(batch.count_skipped(), 0);
+  assert_eq!(
+    batch.into_result().unwrap_err().iter().next().unwrap(),
+    "has_version: no version field"
+  );
+}
+
+#[test]
+fn test_rule_bad_prerequisites() {
+  use allwhat::prelude::*;
+
+  let pass = |_: &u32| Ok::<(), String>(());
+  let rules = vec![
+    Rule::new("is_number", pass),
+    Rule::new("typo", pass).requires("is_numbr"),
+    Rule::new("loop_a", pass)
+      .requires("loop_b")
+      .requires("is_number"),
+    Rule::new("loop_b", pass).requires("loop_a"),
+    Rule::new("after_loop", pass).requires("loop_a"),
+  ];
+
+  // The rules never run, so the value mustn't be let through
+  let clock = MonotonicClock::default();
+  for batch in [
+    BatchResult::validate_rules(5, &rules),
+    BatchResult::validate_rules_timed(5, &rules, &clock),
+  ] {
+    assert!(!batch.is_ok());
+    assert_eq!(batch.count(), 4);
+    assert_eq!(
+      batch.failed_rules().collect::<Vec<_>>(),
+      vec!["typo", "loop_a", "loop_b"]
+    );
+    assert_eq!(
+      batch.rule_outcome("typo"),
+      Some(&RuleOutcome::Failed(
+        "requires unknown rules is_numbr".to_string()
+      ))
+    );
+    assert_eq!(
+      batch.rule_outcome("loop_a"),
+      Some(&RuleOutcome::Failed(
+        "requires rules that form a cycle: loop_b".to_string()
+      ))
+    );
+    assert_eq!(
+      batch.skipped_rules().collect::<Vec<_>>(),
+      vec!["after_loop"]
+    );
+    assert!(batch
+      .to_string()
+      .starts_with("Batch Errors: 1 of 4 passed, 1 skipped, 3 errors"));
+  }
+}
+
+/// A clock that only moves when told to
+#[derive(Default)]
+struct ManualClock {
+  now: std::cell::Cell<std::time::Duration>,
+}
+
+impl ManualClock {
+  fn advance(&self, millis: u64) {
+    self
+      .now
+      .set(self.now.get() + std::time::Duration::from_millis(millis));
+  }
+}
+
+impl allwhat::Clock for ManualClock {
+  fn now(&self) -> std::time::Duration {
+    self.now.get()
+  }
+}
+
+/// Sum the items, pretending each one takes its own value in milliseconds
+fn timed_sum(clock: &ManualClock) -> allwhat::BatchResult<u64> {
+  use allwhat::prelude::*;
+
+  BatchResult::fold_timed(0, 1..=20, clock, |acc, x| {
+    clock.advance(x);
+    match x % 10 {
+      0 => Err(format!("Bad value {}", x)),
+      _ => {
+        *acc += x;
+        Ok(())
+      }
+    }
+  })
+}
+
+#[test]
+fn test_timing() {
+  use std::time::Duration;
+
+  let clock = ManualClock::default();
+  let batch = timed_sum(&clock);
+  assert_eq!(batch.count(), 20);
+  assert_eq!(batch.count_error(), 2);
+
+  let metrics = batch.metrics().unwrap();
+  assert_eq!(metrics.steps()[2].label, "item 3");
+  assert_eq!(metrics.steps()[2].duration, Duration::from_millis(3));
+
+  let summary = metrics.summary();
+  assert_eq!(summary.steps, 20);
+  assert_eq!(summary.total, Duration::from_millis(210));
+  assert_eq!(summary.mean, Duration::from_micros(10_500));
+  assert_eq!(summary.p95, Duration::from_millis(19));
+  let slowest: Vec<_> = summary.slowest.iter().map(|s| s.label.as_str()).collect();
+  assert_eq!(
+    slowest,
+    vec!["item 20", "item 19", "item 18", "item 17", "item 16"]
+  );
+
+  // Untimed runs don't carry any metrics
+  let batch = allwhat::BatchResult::fold(0, 1..=3, |acc, x| {
+    *acc += x;
+    Ok::<(), String>(())
+  });
+  assert!(batch.metrics().is_none());
+}
+
+#[cfg(feature = "serializable")]
+#[test]
+fn test_timing_serialized() {
+  let clock = ManualClock::default();
+  let json = serde_json::to_value(timed_sum(&clock)).unwrap();
+
+  assert_eq!(json["value"], 180);
+  assert_eq!(json["metrics"]["summary"]["steps"], 20);
+  assert_eq!(json["metrics"]["summary"]["p95"]["nanos"], 19_000_000);
+  assert_eq!(json["metrics"]["steps"][0]["label"], "item 1");
+}
+
+#[test]
+fn test_rollback() {
+  use allwhat::prelude::*;
+  use std::collections::HashMap;
+
+  // Each item debits one account and credits another, failing between the two if the credit
+  // account is unknown
+  let accounts: HashMap<&str, i32> = vec![("a", 100), ("b", 0)].into_iter().collect();
+  let transfers = vec![("a", "b", 30), ("a", "x", 50), ("b", "a", 10)];
+  let transfer = |accounts: &mut HashMap<&str, i32>, (from, to, amount): (&str, &str, i32)| {
+    *accounts.get_mut(from).unwrap() -= amount;
+    match accounts.get_mut(to) {
+      Some(balance) => {
+        *balance += amount;
+        Ok(())
+      }
+      None => Err(format!("Unknown account {}", to)),
+    }
+  };
+
+  let corrupted = BatchResult::fold(accounts.clone(), transfers.clone().into_iter(), transfer);
+  assert_eq!(corrupted.value()["a"], 30);
+
+  let batch = BatchResult::fold_or_rollback_cloned(accounts, transfers.into_iter(), transfer);
+  assert_eq!(batch.count(), 3);
+  assert_eq!(batch.count_error(), 1);
+  assert_eq!(batch.value()["a"], 80);
+  assert_eq!(batch.value()["b"], 20);
+
+  let batch = batch.apply_or_rollback_cloned(|accounts| {
+    accounts.insert("c", 5);
+    Err("Account c isn't allowed")
+  });
+  assert_eq!(batch.count_error(), 2);
+  assert!(!batch.value().contains_key("c"));
+
+  // A Clone type can still save less than a full copy
+  #[derive(Clone)]
+  struct Ledger {
+    entries: Vec<i32>,
+  }
+
+  impl Snapshot for Ledger {
+    type Saved = usize;
+
+    fn snapshot(&self) -> usize {
+      self.entries.len()
+    }
+
+    fn restore(&mut self, saved: usize) {
+      self.entries.truncate(saved);
+    }
+  }
+
+  let ledger = Ledger { entries: vec![] };
+  let batch = BatchResult::fold_or_rollback(ledger, vec![5, -3, 2].into_iter(), |ledger, x| {
+    ledger.entries.push(x);
+    match x > 0 {
+      true => Ok(()),
+      false => Err(format!("{} is not a credit", x)),
+    }
+  });
+  assert_eq!(batch.count_error(), 1);
+  assert_eq!(batch.value().entries, vec![5, 2]);
+}
+
+#[test]
+fn test_nested() {
+  use allwhat::prelude::*;
+
+  let positive = |x: &i32| match *x > 0 {
+    true => Ok(()),
+    false => Err(format!("{} is not positive", x)),
+  };
+  let small = |x: &i32| match *x < 100 {
+    true => Ok(()),
+    false => Err(format!("{} is too big", x)),
+  };
+
+  let mut order = BatchResult::validate(
+    "A-1",
+    vec![|id: &&str| match id.is_empty() {
+      true => Err("Missing order id"),
+      false => Ok(()),
+    }]
+    .into_iter(),
+  )
+  .set_label("Order A-1");
+  for (i, quantity) in vec![5, -1, 500].into_iter().enumerate() {
+    let line = BatchResult::validate(quantity, vec![positive, small].into_iter());
+    order.push_child(&format!("Line {}", i + 1), line);
+  }
+
+  assert_eq!(order.count(), 7);
+  assert_eq!(order.count_error(), 2);
+  assert_eq!(order.count_valid(), 5);
+  assert_eq!(order.children().count(), 3);
+  let report = order.to_string();
+  let lines: Vec<_> = report.lines().collect();
+  assert_eq!(lines.len(), 6);
+  assert_eq!(lines[0], "Order A-1: 5 of 7 passed, 2 errors");
+  assert_eq!(lines[1], "\tLine 1: 2 of 2 passed, ok");
+  assert_eq!(lines[2], "\tLine 2: 1 of 2 passed, 1 error");
+  assert!(lines[3].starts_with("\t\t1) ") && lines[3].contains("-1 is not positive"));
+  assert_eq!(lines[4], "\tLine 3: 1 of 2 passed, 1 error");
+
+  let errors = order.into_result().unwrap_err();
+  assert_eq!(errors.len(), 2);
+  let labels: Vec<_> = errors.groups().iter().map(|g| g.label()).collect();
+  assert_eq!(labels, vec![Some("Line 2"), Some("Line 3")]);
+
+  // Merging keeps the errors flat and sums the counts
+  let first = BatchResult::validate(-5, vec![positive, small].into_iter());
+  let second = BatchResult::validate(200, vec![positive, small].into_iter());
+  let merged = first.merge(second);
+  assert_eq!(merged.count(), 4);
+  assert_eq!(merged.count_error(), 2);
+  assert_eq!(*merged.value(), -5);
+  assert!(merged.into_result().unwrap_err().groups().is_empty());
+}
+
+#[cfg(feature = "try_trait")]
+#[test]
+fn test_try_operator() {
+  use allwhat::prelude::*;
+
+  fn total(list: Vec<i32>) -> Result<i32, ErrorGroup> {
+    let sum = BatchResult::fold(0, list.into_iter(), |acc, x| match x < 0 {
+      true => Err(format!("Negative value {}", x)),
+      false => {
+        *acc += x;
+        Ok(())
+      }
+    })?;
+    Ok(sum)
+  }
+
+  assert_eq!(total(vec![1, 2, 3]).unwrap(), 6);
+  assert_eq!(total(vec![1, -2, -3]).unwrap_err().len(), 2);
+
+  // Works for accumulators without a Default, and a thrown batch keeps its passing items
+  struct Total(i32);
+
+  fn checked(list: Vec<i32>) -> BatchResult<Total> {
+    let sum = BatchResult::fold(Total(0), list.into_iter(), |acc, x| match x < 0 {
+      true => Err(format!("Negative value {}", x)),
+      false => {
+        acc.0 += x;
+        Ok(())
+      }
+    })?;
+    BatchResult::new(Total(sum.0 * 2))
+  }
+
+  assert_eq!(checked(vec![1, 2]).value().0, 6);
+  let failed = checked(vec![1, -2, 3]);
+  assert_eq!(failed.count(), 3);
+  assert_eq!(failed.count_error(), 1);
+  assert_eq!(failed.count_valid(), 2);
+}
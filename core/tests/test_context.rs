@@ -0,0 +1,39 @@
+//! Testing for the context chained onto errors
+
+use allwhat::prelude::*;
+
+#[test]
+fn test_context() {
+  let mut group = ErrorGroup::new(None);
+  group.push("Unknown key 'hots'");
+  group.push("Missing key 'port'");
+  group.push("Invalid value for 'user'");
+
+  // Each context wraps the previous one instead of replacing it
+  let group = group
+    .context("parsing section [db]".to_string())
+    .context("loading config".to_string());
+  assert_eq!(
+    group.to_string(),
+    "loading config -> parsing section [db] -> 3 errors:\n\t1) Unknown key 'hots'\n\t2) Missing key 'port'\n\t3) Invalid value for 'user'\n"
+  );
+
+  // The label is the innermost link in the chain
+  let batch = BatchResult::validate(10, vec![|_: &i32| Err("Always fails")].into_iter())
+    .set_label("Checks")
+    .context("validating row 4".to_string());
+  let err: ErrorGroup = batch.as_result().unwrap_err();
+  assert_eq!(err.contexts(), ["validating row 4".to_string()]);
+  assert!(err
+    .to_string()
+    .starts_with("validating row 4 -> Checks -> 1 error:\n"));
+
+  // The lazy version is only evaluated when there are errors to describe
+  let clean = BatchResult::validate(10, vec![|_: &i32| Ok::<_, String>(())].into_iter())
+    .with_context(|| panic!("Should not build a context for a clean result"));
+  assert!(clean.is_ok());
+
+  let split = SplitResult::map(vec![1, 2].into_iter(), |x| Ok::<_, String>(x * 2))
+    .with_context(|| panic!("Should not build a context for a clean result"));
+  assert_eq!(split.as_result::<ErrorGroup>().unwrap(), vec![2, 4]);
+}
@@ -0,0 +1,49 @@
+//! Round trips between ErrorGroup and the dynamic error types
+
+#[cfg(feature = "anyhow")]
+#[test]
+fn test_anyhow() {
+  use allwhat::prelude::*;
+  use anyhow::Context;
+  use std::convert::TryFrom;
+
+  fn parse(list: &[&str]) -> anyhow::Result<Vec<i32>> {
+    let values = SplitResult::map(list.iter(), |x| x.parse::<i32>())
+      .context("parsing values".to_string())
+      .into_result()?;
+    Ok(values)
+  }
+
+  let err = parse(&["1", "two", "three"]).unwrap_err();
+  let display = err.to_string();
+  let group = ErrorGroup::try_from(err).unwrap();
+  assert_eq!(group.len(), 2);
+  assert_eq!(group.contexts(), ["parsing values".to_string()]);
+  assert_eq!(group.to_string(), display);
+
+  // An anyhow context on top of the group doesn't get in the way of the downcast
+  let err = parse(&["x"]).context("loading file").unwrap_err();
+  assert_eq!(ErrorGroup::try_from(err).unwrap().len(), 1);
+
+  // Other errors are handed back untouched
+  let err = ErrorGroup::try_from(anyhow::anyhow!("Not a group")).unwrap_err();
+  assert_eq!(err.to_string(), "Not a group");
+}
+
+#[cfg(feature = "eyre")]
+#[test]
+fn test_eyre() {
+  use allwhat::prelude::*;
+  use std::convert::TryFrom;
+
+  fn parse(list: &[&str]) -> eyre::Result<Vec<i32>> {
+    Ok(SplitResult::map(list.iter(), |x| x.parse::<i32>()).into_result()?)
+  }
+
+  let report = parse(&["1", "two", "three"]).unwrap_err();
+  let group = ErrorGroup::try_from(report).unwrap();
+  assert_eq!(group.len(), 2);
+
+  let report = ErrorGroup::try_from(eyre::eyre!("Not a group")).unwrap_err();
+  assert_eq!(report.to_string(), "Not a group");
+}
@@ -0,0 +1,104 @@
+//! The parallel loops should give the same results as the sequential ones
+#![cfg(feature = "rayon")]
+
+use allwhat::prelude::*;
+
+/// Fail on every multiple of 7, spinning a little so the threads finish out of order
+fn check(x: u64) -> Result<u64, String> {
+  let spin = (x * 7919) % 1000;
+  let hash = (0..spin).fold(x, |acc, i| acc.wrapping_mul(31).wrapping_add(i));
+  match x % 7 {
+    0 => Err(format!("Multiple of seven: {}", x)),
+    _ => Ok(hash),
+  }
+}
+
+#[test]
+fn test_par_map() {
+  let sequential = SplitResult::map(0..10_000u64, check);
+  let parallel = SplitResult::par_map(0..10_000u64, check);
+
+  assert_eq!(parallel.values(), sequential.values());
+  assert_eq!(parallel.failed_indices(), sequential.failed_indices());
+  assert_eq!(
+    parallel.errors().unwrap().to_string(),
+    sequential.errors().unwrap().to_string()
+  );
+}
+
+#[test]
+fn test_par_foreach() {
+  let func = |x: u64| check(x).map(|_| ());
+  let sequential = BatchResult::<()>::foreach(0..10_000u64, &mut { func });
+  let parallel = BatchResult::par_foreach(0..10_000u64, &func);
+
+  assert_eq!(parallel.count(), 10_000);
+  assert_eq!(parallel.count_error(), sequential.count_error());
+  assert_eq!(
+    parallel.as_result::<ErrorGroup>().unwrap_err().to_string(),
+    sequential
+      .as_result::<ErrorGroup>()
+      .unwrap_err()
+      .to_string()
+  );
+}
+
+#[test]
+fn test_par_validate() {
+  let values: Vec<u64> = (0..2_000).collect();
+  let tests: Vec<_> = (1..=50u64)
+    .map(|n| {
+      move |values: &Vec<u64>| {
+        let failures = values.iter().filter(|&&x| check(x).is_err()).count() as u64;
+        match failures % n {
+          0 => Ok(()),
+          rem => Err(format!("Rule {} left {}", n, rem)),
+        }
+      }
+    })
+    .collect();
+  let sequential = BatchResult::validate(values.clone(), tests.clone().into_iter());
+  let parallel = BatchResult::par_validate(values.clone(), tests);
+
+  assert_eq!(parallel.count(), 50);
+  assert_eq!(parallel.count_error(), sequential.count_error());
+  let parallel_errors: Vec<_> = parallel.into_result().unwrap_err().into_iter().collect();
+  let sequential_errors: Vec<_> = sequential.into_result().unwrap_err().into_iter().collect();
+  assert_eq!(parallel_errors, sequential_errors);
+
+  let rules: Vec<_> = (1..=20u64)
+    .map(|n| {
+      Rule::new(&format!("rule {}", n), move |x: &u64| {
+        check(*x + n).map(|_| ())
+      })
+    })
+    .collect();
+  let batch = BatchResult::par_validate_rules(6, &rules);
+  assert_eq!(batch.count(), 20);
+  assert_eq!(
+    batch.failed_rules().collect::<Vec<_>>(),
+    vec!["rule 1", "rule 8", "rule 15"]
+  );
+}
+
+#[test]
+fn test_par_validate_rules_dependencies() {
+  let rules: Vec<_> = (1..=20u64)
+    .map(|n| {
+      let rule = Rule::new(&format!("rule {}", n), move |x: &u64| {
+        check(*x + n).map(|_| ())
+      });
+      match n {
+        1 => rule,
+        _ => rule.requires(&format!("rule {}", n - 1)),
+      }
+    })
+    .collect();
+  let sequential = BatchResult::validate_rules(3, &rules);
+  let parallel = BatchResult::par_validate_rules(3, &rules);
+
+  assert_eq!(parallel.failed_rules().collect::<Vec<_>>(), vec!["rule 4"]);
+  assert_eq!(parallel.count(), 4);
+  assert_eq!(parallel.count_skipped(), 16);
+  assert_eq!(parallel.rule_reports(), sequential.rule_reports());
+}
@@ -0,0 +1,96 @@
+//! Testing for the built in validation rules
+#![cfg(feature = "rules")]
+
+use allwhat::{field, prelude::*, rules};
+
+/// The message from checking a single value against a rule, if it failed
+fn failure<T>(rule: &Rule<T>, value: T) -> Option<String> {
+  match rule.check(&value) {
+    RuleOutcome::Failed(msg) => Some(msg),
+    _ => None,
+  }
+}
+
+#[test]
+fn test_numbers_and_sets() {
+  let range = rules::range(1, 10);
+  assert_eq!(failure(&range, 10), None);
+  assert_eq!(failure(&range, 11).unwrap(), "11 is not between 1 and 10");
+
+  let status = rules::one_of(vec!["open", "closed"]);
+  assert_eq!(failure(&status, "open".to_string()), None);
+  assert_eq!(
+    failure(&status, "lost".to_string()).unwrap(),
+    "'lost' is not one of [open, closed]"
+  );
+}
+
+#[test]
+fn test_strings() {
+  assert!(failure(&rules::not_empty(), "  ").is_some());
+  assert_eq!(
+    failure(&rules::length(2, 3), "abcd").unwrap(),
+    "'abcd' is longer than 3 characters"
+  );
+  assert!(rules::pattern::<&str>("[").is_err());
+  let sku = rules::pattern(r"^[A-Z]{3}-\d+$").unwrap();
+  assert_eq!(failure(&sku, "ABC-12"), None);
+  assert!(failure(&sku, "abc-12").is_some());
+
+  for valid in ["a.b+c@example.co.uk", "x@y.io"] {
+    assert_eq!(failure(&rules::email(), valid), None, "{}", valid);
+  }
+  for invalid in ["plain", "a@b", "@example.com", "a b@example.com"] {
+    assert!(failure(&rules::email(), invalid).is_some(), "{}", invalid);
+  }
+
+  assert_eq!(failure(&rules::url(), "https://example.com/a?b=c"), None);
+  assert!(failure(&rules::url(), "example.com").is_some());
+
+  assert_eq!(
+    failure(&rules::uuid(), "67e55044-10b1-426f-9247-bb680e5fe0c8"),
+    None
+  );
+  assert!(failure(&rules::uuid(), "67e55044-10b1-426f-9247").is_some());
+
+  for valid in [
+    "2020-02-29",
+    "2021-12-31T23:59:59Z",
+    "2021-06-01T08:30:00.250+02:00",
+  ] {
+    assert_eq!(failure(&rules::iso8601(), valid), None, "{}", valid);
+  }
+  for invalid in [
+    "2021-02-29",
+    "2021-13-01",
+    "2021-06-01T24:00:00",
+    "01/06/2021",
+  ] {
+    assert!(failure(&rules::iso8601(), invalid).is_some(), "{}", invalid);
+  }
+}
+
+#[test]
+fn test_coded_messages() {
+  struct Signup {
+    email: String,
+    age: u32,
+  }
+
+  let signup_rules = field(|s: &Signup| &s.email, rules::email())
+    .and(field(|s: &Signup| &s.age, rules::range(13, 130)));
+  let signup = Signup {
+    email: "nobody".to_string(),
+    age: 7,
+  };
+
+  let errors = signup_rules.validate(&signup).unwrap_err();
+  let messages: Vec<_> = errors.iter().collect();
+  assert_eq!(
+    messages,
+    vec![
+      "email: 'nobody' is not a valid email address",
+      "range: 7 is not between 13 and 130"
+    ]
+  );
+}
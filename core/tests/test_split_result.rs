@@ -0,0 +1,331 @@
+//! Testing for the SplitResult
+
+use allwhat::{prelude::*, Sleeper};
+
+/// Parse a list of strings, failing on any that aren't numbers
+fn parse_all(list: &[&str]) -> SplitResult<i32> {
+  SplitResult::map(list.iter(), |x| x.parse::<i32>())
+}
+
+#[test]
+fn test_into_result() {
+  fn sum(list: &[&str]) -> Result<i32, ErrorGroup> {
+    let values = parse_all(list).into_result()?;
+    Ok(values.iter().sum())
+  }
+
+  assert_eq!(sum(&["1", "2", "3"]).unwrap(), 6);
+  assert_eq!(sum(&["1", "two", "three"]).unwrap_err().len(), 2);
+}
+
+#[test]
+fn test_accessors() {
+  let split = parse_all(&["1", "2", "x", "4", "y", "6"]);
+  assert!(!split.is_ok());
+  assert_eq!(split.count(), 6);
+  assert_eq!(split.count_valid(), 4);
+  assert_eq!(split.count_error(), 2);
+  assert_eq!(split.values(), &[1, 2, 4, 6]);
+  assert_eq!(split.failed_indices(), &[2, 4]);
+  assert_eq!(split.errors().unwrap().len(), 2);
+
+  // The good values survive even though there were errors
+  let (values, errors) = split.into_parts();
+  assert_eq!(values, vec![1, 2, 4, 6]);
+  assert_eq!(errors.unwrap().iter().count(), 2);
+
+  let clean = parse_all(&["1", "2"]);
+  assert!(clean.is_ok());
+  assert!(clean.errors().is_none());
+  assert!(clean.failed_indices().is_empty());
+}
+
+#[test]
+fn test_dead_letter() {
+  let rows = vec![
+    "1".to_string(),
+    "x".to_string(),
+    "3".to_string(),
+    "y".to_string(),
+  ];
+  let (split, dead) = SplitResult::map_dead_letter(rows.into_iter(), |x| x.parse::<i32>());
+  assert_eq!(split.values(), &[1, 3]);
+  assert_eq!(split.failed_indices(), &[1, 3]);
+
+  // The original inputs come back with their errors, so they can be fixed and run again
+  let inputs: Vec<&str> = dead.iter().map(|(input, _)| input.as_str()).collect();
+  assert_eq!(inputs, vec!["x", "y"]);
+  assert_eq!(dead[0].1.to_string(), "invalid digit found in string");
+
+  let fixed = dead
+    .into_iter()
+    .map(|(input, _)| input.replace("x", "2").replace("y", "4"));
+  let retried = SplitResult::map(fixed, |x| x.parse::<i32>());
+  assert_eq!(retried.into_result().unwrap(), vec![2, 4]);
+}
+
+#[test]
+fn test_error_budget() {
+  let rows = || (0..100).map(|i| (i, i % 3 == 0));
+  let check = |(i, ok): (i32, bool)| match ok {
+    true => Ok(i),
+    false => Err(format!("Row {} failed", i)),
+  };
+
+  // Without a budget, everything is processed
+  let split = SplitResult::map_with_budget(rows(), &ErrorBudget::new(), check);
+  assert_eq!(split.count(), 100);
+  assert!(split.aborted().is_none());
+
+  let split = SplitResult::map_with_budget(rows(), &ErrorBudget::new().max_errors(5), check);
+  assert_eq!(split.count_error(), 5);
+  assert_eq!(split.values(), &[0, 3, 6]);
+  let aborted = split.aborted().unwrap();
+  assert_eq!(aborted.processed, 8);
+  assert_eq!(aborted.remaining, Some(92));
+
+  // Stop on the first error of a fatal class
+  let budget = ErrorBudget::new().stop_on(|err: &String| err.contains("Row 10 "));
+  let split = SplitResult::map_with_budget(rows(), &budget, check);
+  assert_eq!(split.count(), 11);
+  assert_eq!(split.aborted().unwrap().remaining, Some(89));
+
+  // A budget can be shared between threads
+  fn shareable<T: Send + Sync>(_: &T) {}
+  shareable(&budget);
+}
+
+#[test]
+fn test_chunked() {
+  let bad = [7, 13, 14, 38];
+  let mut committed = vec![];
+  let mut calls = 0;
+
+  let result = SplitResult::chunked(0..40, 10, |chunk: &[i32]| {
+    calls += 1;
+    match chunk.iter().find(|x| bad.contains(x)) {
+      Some(x) => Err(format!("Row {} violates a constraint", x)),
+      None => {
+        committed.extend_from_slice(chunk);
+        Ok(())
+      }
+    }
+  });
+
+  // Everything but the bad rows made it in, in order
+  let expected: Vec<i32> = (0..40).filter(|x| !bad.contains(x)).collect();
+  assert_eq!(committed, expected);
+  assert_eq!(result.items().values(), expected.as_slice());
+
+  // Chunk 2 committed whole, the other three failed and were bisected
+  assert!(!result.is_ok());
+  assert_eq!(result.chunks().count(), 4);
+  assert_eq!(result.chunks().count_error(), 3);
+  assert_eq!(result.items().failed_indices(), &[7, 13, 14, 38]);
+  assert!(calls < 40);
+
+  let (items, chunks) = result.into_parts();
+  assert_eq!(items.count_error(), 4);
+  assert_eq!(chunks.count_valid(), 1);
+}
+
+#[test]
+fn test_retry() {
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+
+  /// Records the waits instead of sleeping, so the test runs instantly
+  #[derive(Clone, Default)]
+  struct FakeSleeper(Arc<Mutex<Vec<Duration>>>);
+
+  impl Sleeper for FakeSleeper {
+    fn sleep(&self, duration: Duration) {
+      self.0.lock().unwrap().push(duration);
+    }
+  }
+
+  let sleeper = FakeSleeper::default();
+  let policy = RetryPolicy::new(4)
+    .backoff(Backoff::Exponential {
+      initial: Duration::from_millis(10),
+      factor: 2,
+      max: Duration::from_millis(25),
+    })
+    .retry_if(|err: &String| err.starts_with("Busy"))
+    .sleeper(sleeper.clone());
+
+  // Item 1 succeeds on the third try, item 2 is always busy, and item 3 fails for good
+  let tries = Mutex::new(vec![0; 4]);
+  let split = SplitResult::map_with_retry(0..4usize, &policy, |i| {
+    let mut tries = tries.lock().unwrap();
+    tries[*i] += 1;
+    match (i, tries[*i]) {
+      (1, 3) | (0, _) => Ok(*i),
+      (1, _) | (2, _) => Err(format!("Busy with {}", i)),
+      _ => Err(format!("Broken {}", i)),
+    }
+  });
+
+  assert_eq!(split.values(), &[0, 1]);
+  assert_eq!(split.failed_indices(), &[2, 3]);
+  assert_eq!(*tries.lock().unwrap(), vec![1, 3, 4, 1]);
+
+  let errors: Vec<&String> = split.errors().unwrap().iter().collect();
+  assert!(errors[0].starts_with("\"Failed after 4 attempts: "));
+  assert!(errors[1].starts_with("\"Failed after 1 attempt: "));
+
+  let ms = |ms| Duration::from_millis(ms);
+  assert_eq!(
+    *sleeper.0.lock().unwrap(),
+    vec![ms(10), ms(20), ms(10), ms(20), ms(25)]
+  );
+}
+
+#[test]
+fn test_classified() {
+  #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+  enum Bucket {
+    Missing,
+    Format,
+  }
+
+  impl std::fmt::Display for Bucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      match self {
+        Bucket::Missing => write!(f, "Missing field"),
+        Bucket::Format => write!(f, "Bad format"),
+      }
+    }
+  }
+
+  let rows = vec!["1", "", "x", "4", "", "y", ""];
+  let (split, buckets) = SplitResult::map_classified(
+    rows.into_iter(),
+    |err: &String| match err.as_str() {
+      "empty" => Bucket::Missing,
+      _ => Bucket::Format,
+    },
+    |row| match row.is_empty() {
+      true => Err("empty".to_string()),
+      false => row.parse::<i32>().map_err(|err| err.to_string()),
+    },
+  );
+
+  // The successes are kept as usual
+  assert_eq!(split.values(), &[1, 4]);
+  assert_eq!(split.count_error(), 5);
+
+  assert_eq!(buckets.len(), 2);
+  assert_eq!(buckets[&Bucket::Missing].len(), 3);
+  assert_eq!(buckets[&Bucket::Format].len(), 2);
+  assert!(buckets[&Bucket::Format]
+    .to_string()
+    .starts_with("Bad format:\n"));
+}
+
+#[test]
+fn test_combinators() {
+  let split = parse_all(&["1", "x", "3", "-4", "5", "12"])
+    .and_then("range check", |x| match x {
+      0..=9 => Ok(x),
+      _ => Err(format!("{} is out of range", x)),
+    })
+    .flat_map("expand", |x| match x {
+      5 => Err("5 can't be expanded"),
+      x => Ok(vec![x; x as usize]),
+    })
+    .map_values(|x| x * 10);
+
+  assert_eq!(split.values(), &[10, 30, 30, 30]);
+  assert_eq!(split.passed_indices(), &[0, 2, 2, 2]);
+  assert_eq!(split.failed_indices(), &[1, 3, 5, 4]);
+  // Six inputs went in, however many values they turned into
+  assert_eq!(split.count(), 6);
+  assert_eq!(split.count_valid(), 4);
+  assert_eq!(split.count_error(), 4);
+
+  let errors = split.errors().unwrap();
+  assert_eq!(errors.len(), 4);
+  let labels: Vec<_> = errors.groups().iter().map(|g| g.label()).collect();
+  assert_eq!(labels, vec![Some("range check"), Some("expand")]);
+  let display = errors.to_string();
+  assert!(display.contains("\trange check:\n\t\t1) "));
+  assert!(display.contains("-4 is out of range"));
+  assert!(display.contains("\texpand:\n\t\t1) "));
+
+  let merged = parse_all(&["1", "x"]).merge(parse_all(&["y", "4"]));
+  assert_eq!(merged.values(), &[1, 4]);
+  assert_eq!(merged.passed_indices(), &[0, 3]);
+  assert_eq!(merged.failed_indices(), &[1, 2]);
+  assert_eq!(merged.count_error(), 2);
+
+  // The last input expands to nothing, but still takes up a place before the merged indices
+  let empty_tail =
+    parse_all(&["1", "0"]).flat_map("expand", |x| Ok::<_, String>(vec![x; x as usize]));
+  let merged = empty_tail.merge(parse_all(&["2"]));
+  assert_eq!(merged.values(), &[1, 2]);
+  assert_eq!(merged.passed_indices(), &[0, 2]);
+}
+
+#[cfg(feature = "try_trait")]
+#[test]
+fn test_try_operator() {
+  #[derive(Debug)]
+  struct AppError(String);
+
+  impl From<ErrorGroup> for AppError {
+    fn from(group: ErrorGroup) -> AppError {
+      AppError(group.to_string())
+    }
+  }
+
+  fn sum(list: &[&str]) -> Result<i32, AppError> {
+    let values = parse_all(list)?;
+    Ok(values.iter().sum())
+  }
+
+  assert_eq!(sum(&["1", "2", "3"]).unwrap(), 6);
+  let err = sum(&["1", "two"]).unwrap_err();
+  assert!(err.0.starts_with("Error Group:\n\t1) "));
+
+  // Errors thrown inside a function returning a SplitResult become its errors
+  fn nested(list: &[&str]) -> SplitResult<i32> {
+    let values = parse_all(list)?;
+    SplitResult::map(values.into_iter(), |x| Ok::<_, String>(x * 2))
+  }
+  assert_eq!(nested(&["1", "2"]).into_result().unwrap(), vec![2, 4]);
+  assert_eq!(nested(&["x", "2"]).into_result().unwrap_err().len(), 1);
+
+  // Each thrown error gets an index, so the indices still line up with the errors
+  let thrown = nested(&["x", "2", "y"]);
+  assert_eq!(thrown.count_error(), 2);
+  assert_eq!(thrown.failed_indices(), &[0, 1]);
+  assert_eq!(thrown.count(), 2);
+}
+
+#[test]
+fn test_split_errors() {
+  let mut group = ErrorGroup::new(Some("Bad Rows".to_string()));
+  let doubled: Vec<i32> = ["1", "x", "3", "y", "5"]
+    .iter()
+    .map(|x| x.parse::<i32>())
+    .split_errors(&mut group)
+    .map(|x| x * 2)
+    .collect();
+  assert_eq!(doubled, vec![2, 6, 10]);
+  assert_eq!(group.len(), 2);
+
+  // Only the consumed part of the input reaches the sink
+  let mut indexed: Vec<(usize, String)> = vec![];
+  let mut lazy = (0..)
+    .map(|i| match i % 3 {
+      0 => Err(format!("{} is a multiple of 3", i)),
+      _ => Ok(i),
+    })
+    .split_errors(&mut indexed);
+  assert_eq!(lazy.next(), Some(1));
+  assert_eq!(lazy.next(), Some(2));
+  assert_eq!(lazy.next(), Some(4));
+  assert_eq!(indexed.len(), 2);
+  assert_eq!(indexed[1], (3, "3 is a multiple of 3".to_string()));
+}
@@ -2,10 +2,11 @@
 
 mod common;
 
-/*
+#[cfg(feature = "try_mut")]
 #[test]
 /// Basic test of mutability of a primitive.
 fn test_i32() {
+  use allwhat::try_mut::{impls::i32TryMut, PoisonedErr, TryMut};
   use common::*;
 
   let mut action = i32TryMut::Op(Box::new(|item: &mut i32| {
@@ -23,7 +24,7 @@ fn test_i32() {
 
   for i in 0..10 {
     println!("Running i: {}", i);
-    let mut j = i.clone();
+    let mut j = i;
     match j.try_mut(&mut action) {
       PoisonedErr::Ok => {
         assert_eq!(j, i + 10);
@@ -36,7 +37,107 @@ fn test_i32() {
     }
   }
 }
-*/
+
+#[cfg(feature = "try_mut")]
+#[test]
+/// Fold actions into a stack, where popping from an empty stack can't be undone
+fn test_try_fold() {
+  use allwhat::{
+    try_mut::{PoisonedErr, TryMut, TryMutAction},
+    BatchResult,
+  };
+  use common::*;
+
+  #[derive(Debug)]
+  struct Stack(Vec<i32>);
+
+  enum StackAction {
+    /// Push a value, rejecting negatives after they have been pushed
+    Push(i32),
+    /// Pop a value, which also breaks the stack if there was nothing to pop
+    Pop,
+  }
+
+  impl TryMutAction for StackAction {
+    type Item = Stack;
+    type Patch = usize;
+    type Error = AnyhowError;
+
+    fn patch(&self, item: &Stack) -> usize {
+      item.0.len()
+    }
+
+    fn run(&mut self, item: &mut Stack, _patch: &mut usize) -> Result<()> {
+      match self {
+        StackAction::Push(x) => {
+          item.0.push(*x);
+          match *x < 0 {
+            true => Err(anyhow!("Negative value {}", x)),
+            false => Ok(()),
+          }
+        }
+        StackAction::Pop => item
+          .0
+          .pop()
+          .map(|_| ())
+          .ok_or_else(|| anyhow!("Nothing to pop")),
+      }
+    }
+
+    fn revert(&self, item: &mut Stack, err: AnyhowError, patch: usize) -> PoisonedErr<AnyhowError> {
+      match self {
+        StackAction::Push(_) => {
+          item.0.truncate(patch);
+          PoisonedErr::Err(err)
+        }
+        StackAction::Pop => PoisonedErr::Poisoned(err, anyhow!("Can't restore an empty stack")),
+      }
+    }
+  }
+
+  impl TryMut for Stack {
+    type Error = AnyhowError;
+    type Action = StackAction;
+
+    fn try_mut(&mut self, action: &mut StackAction) -> PoisonedErr<AnyhowError> {
+      let mut patch = action.patch(self);
+      match action.run(self, &mut patch) {
+        Ok(_) => PoisonedErr::Ok,
+        Err(err) => action.revert(self, err, patch),
+      }
+    }
+  }
+
+  let actions = vec![
+    StackAction::Push(1),
+    StackAction::Push(-2),
+    StackAction::Push(3),
+    StackAction::Pop,
+  ];
+  let batch = BatchResult::try_fold(Stack(vec![]), actions.into_iter());
+  assert_eq!(batch.count(), 4);
+  assert_eq!(batch.count_error(), 1);
+  assert!(!batch.is_poisoned());
+  assert_eq!(batch.value().0, vec![1]);
+
+  let actions = vec![StackAction::Push(1), StackAction::Pop, StackAction::Pop];
+  let batch = BatchResult::try_fold(Stack(vec![]), actions.into_iter());
+  assert_eq!(batch.count_error(), 1);
+  assert!(batch.is_poisoned());
+  let fatal: Vec<_> = batch.fatal_errors().unwrap().iter().collect();
+  assert_eq!(
+    fatal,
+    vec!["Nothing to pop - the revert failed with: Can't restore an empty stack"]
+  );
+
+  // Trying an action on the batch itself records the outcome and also hands it back
+  let mut batch = BatchResult::new(Stack(vec![]));
+  let outcome = batch.try_mut(&mut StackAction::Push(-1));
+  assert!(matches!(outcome, PoisonedErr::Err(_)));
+  assert_eq!(batch.count(), 1);
+  assert_eq!(batch.count_error(), 1);
+  assert!(batch.value().0.is_empty());
+}
 
 /*
 #[test]
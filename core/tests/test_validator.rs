@@ -0,0 +1,118 @@
+//! Testing for composed validators
+
+use allwhat::{each, field, prelude::*};
+
+#[derive(Debug)]
+struct Line {
+  sku: String,
+  quantity: i32,
+}
+
+#[derive(Debug)]
+struct Order {
+  id: String,
+  gift: bool,
+  message: String,
+  lines: Vec<Line>,
+}
+
+fn not_empty(name: &str) -> Rule<String> {
+  Rule::new(name, |x: &String| match x.is_empty() {
+    true => Err("is empty"),
+    false => Ok(()),
+  })
+}
+
+fn positive() -> Rule<i32> {
+  Rule::new("positive", |x: &i32| match *x > 0 {
+    true => Ok(()),
+    false => Err(format!("{} is not positive", x)),
+  })
+}
+
+fn order() -> Order {
+  Order {
+    id: "A-1".to_string(),
+    gift: true,
+    message: String::new(),
+    lines: vec![
+      Line {
+        sku: "X".to_string(),
+        quantity: 2,
+      },
+      Line {
+        sku: String::new(),
+        quantity: -1,
+      },
+    ],
+  }
+}
+
+#[test]
+fn test_composed() {
+  let line_rules =
+    field(|l: &Line| &l.sku, not_empty("sku")).and(field(|l: &Line| &l.quantity, positive()));
+  let order_rules = field(|o: &Order| &o.id, not_empty("id"))
+    .and(field(|o: &Order| &o.message, not_empty("message")).when(|o: &Order| o.gift))
+    .and(field(|o: &Order| &o.lines, each(&line_rules)));
+
+  let batch = BatchResult::validate_with(order(), &order_rules);
+  assert_eq!(batch.count(), 6);
+  assert_eq!(batch.count_error(), 3);
+
+  let errors = batch.into_result().unwrap_err();
+  assert_eq!(errors.groups().len(), 1);
+  assert_eq!(errors.groups()[0].label(), Some("Item 1"));
+  let messages: Vec<_> = errors.iter().collect();
+  assert_eq!(
+    messages,
+    vec![
+      "message: is empty",
+      "sku: is empty",
+      "positive: -1 is not positive"
+    ]
+  );
+
+  // The same line rules can be reused on their own
+  let line = Line {
+    sku: "Y".to_string(),
+    quantity: 3,
+  };
+  assert!(line_rules.validate(&line).is_ok());
+}
+
+#[test]
+fn test_or_not() {
+  let small = Rule::new("small", |x: &i32| match *x < 10 {
+    true => Ok(()),
+    false => Err(format!("{} is too big", x)),
+  });
+  let even = Rule::new("even", |x: &i32| match x % 2 {
+    0 => Ok(()),
+    _ => Err(format!("{} is odd", x)),
+  });
+  let small_or_even = (&small).or(&even);
+
+  assert!(small_or_even.validate(&3).is_ok());
+  assert!(small_or_even.validate(&20).is_ok());
+  let errors = small_or_even.validate(&21).unwrap_err();
+  assert_eq!(errors.len(), 2);
+  assert_eq!(
+    errors.groups()[0].label(),
+    Some("None of the alternatives passed")
+  );
+
+  // With nothing to check on either side, the Or passes without counting a check
+  let all_small = Validator::<Vec<i32>>::or(each(&small), each(&small));
+  let mut errors = ErrorGroup::new(None);
+  assert_eq!(all_small.validate_into(&vec![], &mut errors), 0);
+  assert!(errors.is_empty());
+
+  let big = small.not("the value must be at least 10");
+  assert!(big.validate(&12).is_ok());
+  let errors = big.validate(&4).unwrap_err();
+  assert_eq!(
+    errors.iter().next().unwrap(),
+    "the value must be at least 10"
+  );
+}
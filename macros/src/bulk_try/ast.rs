@@ -229,7 +229,7 @@ impl Questionable for ExprTry {
           match #expr_value {
             Ok(val) => Ok(val),
             Err(err) => {
-              __error_group.append(anyhow::anyhow!(err));
+              __error_group.append(err);
               Err(())
             },
           },